@@ -0,0 +1,78 @@
+extern crate gfx;
+extern crate gfx_graphics;
+extern crate glutin_window;
+extern crate graphics;
+extern crate piston;
+
+use gfx::format::{DepthStencil, Formatted, Srgba8};
+use gfx::memory::Typed;
+use gfx::traits::*;
+use gfx_graphics::{DynamicTexture, Gfx2d, TextureContext, TextureSettings};
+use glutin_window::{GlutinWindow, OpenGL};
+use piston::event_loop::*;
+use piston::input::*;
+use piston::window::{OpenGLWindow, Window, WindowSettings};
+
+fn main() {
+    let opengl = OpenGL::V3_2;
+    let (w, h) = (640, 480);
+    let mut window: GlutinWindow = WindowSettings::new("gfx_graphics: dynamic_texture", [w, h])
+        .exit_on_esc(true)
+        .graphics_api(opengl)
+        .build()
+        .unwrap();
+    let (mut device, mut factory) =
+        gfx_device_gl::create(|s| window.get_proc_address(s) as *const std::os::raw::c_void);
+
+    let mut texture_context = TextureContext {
+        factory: factory.clone(),
+        encoder: factory.create_command_buffer().into(),
+    };
+    let size = [256, 256];
+    let mut canvas = DynamicTexture::new(&mut texture_context, size, true,
+                                         &TextureSettings::new()).unwrap();
+    texture_context.encoder.flush(&mut device);
+
+    let mut encoder = factory.create_command_buffer().into();
+    let draw_size = window.draw_size();
+    let dim = (draw_size.width as u16, draw_size.height as u16, 1,
+               gfx::texture::AaMode::Single);
+    let color_format = <Srgba8 as Formatted>::get_format();
+    let depth_format = <DepthStencil as Formatted>::get_format();
+    let (output_color, output_stencil) =
+        gfx_device_gl::create_main_targets_raw(dim, color_format.0, depth_format.0);
+    let output_color = Typed::new(output_color);
+    let output_stencil = Typed::new(output_stencil);
+    let mut g2d = Gfx2d::new(opengl, &mut factory);
+    let mut frame = 0u32;
+    let mut row = vec![0; size[0] as usize * 4 * 8];
+    let mut events = Events::new(EventSettings::new());
+    while let Some(e) = events.next(&mut window) {
+        if let Some(args) = e.render_args() {
+            g2d.draw(&mut encoder, &output_color, &output_stencil, args.viewport(), |c, g| {
+                use graphics::*;
+
+                // Redraw a band of 8 rows each frame.
+                let y = frame * 8 % size[1];
+                for (i, pixel) in row.chunks_mut(4).enumerate() {
+                    let x = i as u32 % size[0];
+                    let v = ((x + frame) % 256) as u8;
+                    pixel.copy_from_slice(&[v, (y % 256) as u8, 255 - v, 255]);
+                }
+                canvas.update_region(g, [0, y, size[0], 8], &row).unwrap();
+                canvas.swap();
+
+                clear([1.0; 4], g);
+                Image::new()
+                    .rect([64.0, 64.0, 352.0, 352.0])
+                    .draw(canvas.texture(), &c.draw_state, c.transform, g);
+            });
+            encoder.flush(&mut device);
+            frame += 1;
+        }
+
+        if e.after_render_args().is_some() {
+            device.cleanup();
+        }
+    }
+}
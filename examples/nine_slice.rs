@@ -0,0 +1,84 @@
+extern crate gfx;
+extern crate gfx_graphics;
+extern crate glutin_window;
+extern crate graphics;
+extern crate image as im;
+extern crate piston;
+
+use gfx::format::{DepthStencil, Formatted, Srgba8};
+use gfx::memory::Typed;
+use gfx::traits::*;
+use gfx_graphics::{Gfx2d, NineSlice, Texture, TextureContext, TextureSettings};
+use glutin_window::{GlutinWindow, OpenGL};
+use piston::event_loop::*;
+use piston::input::*;
+use piston::window::{OpenGLWindow, Window, WindowSettings};
+
+fn main() {
+    println!("Press T to toggle tiled edges");
+
+    let opengl = OpenGL::V3_2;
+    let (w, h) = (640, 480);
+    let mut window: GlutinWindow = WindowSettings::new("gfx_graphics: nine_slice", [w, h])
+        .exit_on_esc(true)
+        .graphics_api(opengl)
+        .build()
+        .unwrap();
+    let (mut device, mut factory) =
+        gfx_device_gl::create(|s| window.get_proc_address(s) as *const std::os::raw::c_void);
+
+    // A panel with a dark border and a striped edge pattern.
+    let img = im::RgbaImage::from_fn(48, 48, |x, y| {
+        let border = x < 12 || y < 12 || x >= 36 || y >= 36;
+        let stripe = (x / 4 + y / 4) % 2 == 0;
+        match (border, stripe) {
+            (true, true) => im::Rgba([40, 40, 60, 255]),
+            (true, false) => im::Rgba([80, 80, 120, 255]),
+            (false, _) => im::Rgba([220, 220, 240, 255]),
+        }
+    });
+    let mut texture_context = TextureContext {
+        factory: factory.clone(),
+        encoder: factory.create_command_buffer().into(),
+    };
+    let texture = Texture::from_image(&mut texture_context, &img, &TextureSettings::new()).unwrap();
+    texture_context.encoder.flush(&mut device);
+
+    let mut encoder = factory.create_command_buffer().into();
+    let draw_size = window.draw_size();
+    let dim = (draw_size.width as u16, draw_size.height as u16, 1,
+               gfx::texture::AaMode::Single);
+    let color_format = <Srgba8 as Formatted>::get_format();
+    let depth_format = <DepthStencil as Formatted>::get_format();
+    let (output_color, output_stencil) =
+        gfx_device_gl::create_main_targets_raw(dim, color_format.0, depth_format.0);
+    let output_color = Typed::new(output_color);
+    let output_stencil = Typed::new(output_stencil);
+    let mut g2d = Gfx2d::new(opengl, &mut factory);
+    let mut panel = NineSlice::new([12.0; 4]);
+    let mut events = Events::new(EventSettings::new().lazy(true));
+    while let Some(e) = events.next(&mut window) {
+        if let Some(Button::Keyboard(Key::T)) = e.press_args() {
+            panel = panel.tile_edges(!panel.tile_edges);
+            println!("Tiled edges: {}", panel.tile_edges);
+        }
+
+        if let Some(args) = e.render_args() {
+            g2d.draw(&mut encoder, &output_color, &output_stencil, args.viewport(), |c, g| {
+                use graphics::*;
+
+                clear([1.0; 4], g);
+                // Panels of different sizes keep the same border width.
+                panel.draw(&texture, [20.0, 20.0, 300.0, 200.0], &c.draw_state, c.transform, g);
+                panel.draw(&texture, [340.0, 20.0, 100.0, 400.0], &c.draw_state, c.transform, g);
+                panel.color([1.0, 0.5, 0.5, 1.0])
+                    .draw(&texture, [20.0, 240.0, 280.0, 60.0], &c.draw_state, c.transform, g);
+            });
+            encoder.flush(&mut device);
+        }
+
+        if e.after_render_args().is_some() {
+            device.cleanup();
+        }
+    }
+}
@@ -0,0 +1,83 @@
+extern crate glutin_window;
+extern crate piston;
+extern crate graphics;
+extern crate gfx_graphics;
+extern crate gfx;
+extern crate gfx_device_gl;
+
+use gfx::traits::*;
+use gfx::format::{DepthStencil, Formatted, Srgba8};
+use gfx::memory::Typed;
+use glutin_window::{GlutinWindow, OpenGL};
+use piston::window::{OpenGLWindow, Window, WindowSettings};
+use piston::event_loop::{Events, EventSettings, EventLoop};
+use graphics::*;
+use piston::input::*;
+use gfx_graphics::{Gfx2d, RenderTarget, TextureSettings};
+
+fn main() {
+    let opengl = OpenGL::V3_2;
+    let mut window: GlutinWindow = WindowSettings::new(
+            "gfx_graphics: render_target",
+            [600, 300]
+        )
+        .exit_on_esc(true)
+        .graphics_api(opengl)
+        .build()
+        .unwrap();
+
+    let (mut device, mut factory) = gfx_device_gl::create(|s|
+        window.get_proc_address(s) as *const std::os::raw::c_void);
+
+    // Create the main color/depth targets without multisampling.
+    let draw_size = window.draw_size();
+    let dim = (draw_size.width as u16, draw_size.height as u16, 1,
+               gfx::texture::AaMode::Single);
+    let color_format = <Srgba8 as Formatted>::get_format();
+    let depth_format = <DepthStencil as Formatted>::get_format();
+    let (output_color, output_stencil) =
+        gfx_device_gl::create_main_targets_raw(dim,
+                                               color_format.0,
+                                               depth_format.0);
+    let output_color = Typed::new(output_color);
+    let output_stencil = Typed::new(output_stencil);
+
+    // Offscreen targets with and without multisampling.
+    let single = RenderTarget::new(&mut factory, 256, 256, 1,
+                                   &TextureSettings::new()).unwrap();
+    let multi = RenderTarget::new(&mut factory, 256, 256, 4,
+                                  &TextureSettings::new()).unwrap();
+
+    let mut encoder = factory.create_command_buffer().into();
+    let mut g2d = Gfx2d::new(opengl, &mut factory);
+    let mut events = Events::new(EventSettings::new().lazy(true));
+    while let Some(e) = events.next(&mut window) {
+        if let Some(args) = e.render_args() {
+            for target in &[&single, &multi] {
+                g2d.draw(&mut encoder, &target.output_color, &target.output_stencil,
+                         target.viewport(), |c, g| {
+                    clear([1.0; 4], g);
+                    Ellipse::new([1.0, 0.0, 0.0, 1.0])
+                        .draw([16.0, 16.0, 224.0, 224.0], &c.draw_state, c.transform, g);
+                    Line::new([0.0, 0.0, 1.0, 1.0], 1.0)
+                        .draw([0.0, 0.0, 256.0, 200.0], &c.draw_state, c.transform, g);
+                });
+                g2d.resolve(&mut encoder, target);
+            }
+
+            g2d.draw(&mut encoder, &output_color, &output_stencil, args.viewport(), |c, g| {
+                clear([0.8, 0.8, 0.8, 1.0], g);
+                // Render targets are stored upside down.
+                let transform = c.transform.trans(22.0, 278.0).flip_v();
+                Image::new().draw(single.texture(), &c.draw_state, transform, g);
+                let transform = c.transform.trans(322.0, 278.0).flip_v();
+                Image::new().draw(multi.texture(), &c.draw_state, transform, g);
+            });
+            encoder.flush(&mut device);
+        }
+
+        if let Some(_) = e.after_render_args() {
+            device.cleanup();
+        }
+    }
+}
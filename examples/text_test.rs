@@ -13,7 +13,7 @@ use std::path::Path;
 use piston::window::{OpenGLWindow, Window, WindowSettings};
 use piston::input::{AfterRenderEvent, RenderEvent};
 use piston::event_loop::{Events, EventSettings, EventLoop};
-use gfx_graphics::{Gfx2d, GlyphCache, TextureSettings, TextureContext};
+use gfx_graphics::{Gfx2d, AtlasGlyphCache, TextureSettings, TextureContext};
 
 fn main() {
     let opengl = OpenGL::V3_2;
@@ -29,7 +29,7 @@ fn main() {
     let (mut device, mut factory) = gfx_device_gl::create(|s|
         window.get_proc_address(s) as *const std::os::raw::c_void);
 
-    let mut glyph_cache = GlyphCache::new(
+    let mut glyph_cache = AtlasGlyphCache::new(
         Path::new("assets/FiraSans-Regular.ttf"),
         TextureContext {
             factory: factory.clone(),
@@ -68,11 +68,10 @@ fn main() {
                     c.transform.trans(10.0, 100.0),
                     g
                 ).unwrap();
+                // Write new glyphs before the text is drawn.
+                glyph_cache.flush_uploads(g);
             });
 
-            // Update glyphs before rendering.
-            glyph_cache.factory.encoder.flush(&mut device);
-            
             encoder.flush(&mut device);
         }
 
@@ -0,0 +1,87 @@
+extern crate gfx;
+extern crate gfx_graphics;
+extern crate glutin_window;
+extern crate graphics;
+extern crate image as im;
+extern crate piston;
+
+use gfx::format::{DepthStencil, Formatted, Srgba8};
+use gfx::memory::Typed;
+use gfx::traits::*;
+use gfx_graphics::{Gfx2d, TextureArray, TextureContext, TextureSettings};
+use glutin_window::{GlutinWindow, OpenGL};
+use piston::event_loop::*;
+use piston::input::*;
+use piston::window::{OpenGLWindow, Window, WindowSettings};
+
+fn main() {
+    let opengl = OpenGL::V3_2;
+    let (w, h) = (640, 480);
+    let mut window: GlutinWindow = WindowSettings::new("gfx_graphics: texture_array", [w, h])
+        .exit_on_esc(true)
+        .graphics_api(opengl)
+        .build()
+        .unwrap();
+    let (mut device, mut factory) =
+        gfx_device_gl::create(|s| window.get_proc_address(s) as *const std::os::raw::c_void);
+
+    let mut texture_context = TextureContext {
+        factory: factory.clone(),
+        encoder: factory.create_command_buffer().into(),
+    };
+
+    // Animation frames of the same size, one per layer.
+    let frames: Vec<im::RgbaImage> = (0..8u32).map(|i| {
+        im::RgbaImage::from_fn(32, 32, |x, y| {
+            let on = (x / 4 + y / 4 + i) % 8 < 4;
+            if on { im::Rgba([255, 128, 0, 255]) } else { im::Rgba([0, 0, 0, 0]) }
+        })
+    }).collect();
+    let frames = TextureArray::from_images(
+        &mut texture_context, &frames, &TextureSettings::new()).unwrap();
+    // Write the layers before drawing them.
+    texture_context.encoder.flush(&mut device);
+
+    let mut encoder = factory.create_command_buffer().into();
+    let draw_size = window.draw_size();
+    let dim = (draw_size.width as u16, draw_size.height as u16, 1,
+               gfx::texture::AaMode::Single);
+    let color_format = <Srgba8 as Formatted>::get_format();
+    let depth_format = <DepthStencil as Formatted>::get_format();
+    let (output_color, output_stencil) =
+        gfx_device_gl::create_main_targets_raw(dim, color_format.0, depth_format.0);
+    let output_color = Typed::new(output_color);
+    let output_stencil = Typed::new(output_stencil);
+    let mut g2d = Gfx2d::new(opengl, &mut factory);
+    assert!(g2d.supports_texture_arrays());
+    g2d.enable_texture_arrays(&mut factory).unwrap();
+    let mut time = 0.0;
+    let mut events = Events::new(EventSettings::new());
+    while let Some(e) = events.next(&mut window) {
+        if let Some(args) = e.update_args() {
+            time += args.dt;
+        }
+
+        if let Some(args) = e.render_args() {
+            g2d.draw(&mut encoder, &output_color, &output_stencil, args.viewport(), |c, g| {
+                use graphics::*;
+
+                clear([1.0; 4], g);
+                // Each sprite shows a different frame, all in one draw call.
+                for i in 0..(w / 32) {
+                    for j in 0..(h / 32) {
+                        let layer = ((time * 8.0) as u32 + i + j) % frames.layers();
+                        let rect = [i as f64 * 32.0, j as f64 * 32.0, 32.0, 32.0];
+                        g.image_layer(&frames, layer, &[1.0; 4], rect,
+                                      &c.draw_state, c.transform);
+                    }
+                }
+            });
+            encoder.flush(&mut device);
+        }
+
+        if e.after_render_args().is_some() {
+            device.cleanup();
+        }
+    }
+}
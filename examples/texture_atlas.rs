@@ -0,0 +1,104 @@
+extern crate gfx;
+extern crate gfx_graphics;
+extern crate glutin_window;
+extern crate graphics;
+extern crate image as im;
+extern crate piston;
+
+use gfx::format::{DepthStencil, Formatted, Srgba8};
+use gfx::memory::Typed;
+use gfx::traits::*;
+use gfx_graphics::{Gfx2d, TextureAtlas, TextureContext, TextureSettings};
+use glutin_window::{GlutinWindow, OpenGL};
+use piston::event_loop::*;
+use piston::input::*;
+use piston::window::{OpenGLWindow, Window, WindowSettings};
+
+fn main() {
+    println!("Press A to add a sprite to the atlas while running");
+
+    let opengl = OpenGL::V3_2;
+    let (w, h) = (640, 480);
+    let mut window: GlutinWindow = WindowSettings::new("gfx_graphics: texture_atlas", [w, h])
+        .exit_on_esc(true)
+        .graphics_api(opengl)
+        .build()
+        .unwrap();
+    let (mut device, mut factory) =
+        gfx_device_gl::create(|s| window.get_proc_address(s) as *const std::os::raw::c_void);
+
+    let mut texture_context = TextureContext {
+        factory: factory.clone(),
+        encoder: factory.create_command_buffer().into(),
+    };
+
+    // Pack logos and generated sprites into one texture.
+    let mut images = vec![
+        im::open("./assets/rust.png").unwrap().to_rgba8(),
+        im::open("./assets/rust-white.png").unwrap().to_rgba8(),
+    ];
+    for i in 0..16 {
+        let c = (i * 16) as u8;
+        images.push(im::RgbaImage::from_pixel(16 + i, 16, im::Rgba([c, 255 - c, 128, 255])));
+    }
+    let mut atlas = TextureAtlas::new(TextureSettings::new()).page_size([1024; 2]);
+    let mut sprites = atlas.add_all(&mut texture_context, &images).unwrap();
+
+    let mut encoder = factory.create_command_buffer().into();
+    let draw_size = window.draw_size();
+    let dim = (draw_size.width as u16, draw_size.height as u16, 1,
+               gfx::texture::AaMode::Single);
+    let color_format = <Srgba8 as Formatted>::get_format();
+    let depth_format = <DepthStencil as Formatted>::get_format();
+    let (output_color, output_stencil) =
+        gfx_device_gl::create_main_targets_raw(dim, color_format.0, depth_format.0);
+    let output_color = Typed::new(output_color);
+    let output_stencil = Typed::new(output_stencil);
+    let mut g2d = Gfx2d::new(opengl, &mut factory);
+    let mut events = Events::new(EventSettings::new().lazy(true));
+    while let Some(e) = events.next(&mut window) {
+        if let Some(Button::Keyboard(Key::A)) = e.press_args() {
+            let c = (sprites.len() * 8 % 256) as u8;
+            let image = im::RgbaImage::from_pixel(24, 24, im::Rgba([255, c, 0, 255]));
+            sprites.push(atlas.add(&mut texture_context, &image).unwrap());
+        }
+
+        if let Some(args) = e.render_args() {
+            g2d.draw(&mut encoder, &output_color, &output_stencil, args.viewport(), |c, g| {
+                use graphics::*;
+
+                clear([1.0; 4], g);
+                // Write added sprites before drawing them.
+                atlas.flush_uploads(g);
+                // All sprites share one texture and are drawn in one call.
+                let (mut x, mut y, mut row) = (0.0, 0.0, 0.0);
+                for sprite in &sprites {
+                    let size = sprite.size();
+                    let (sw, sh) = (size[0] as f64 * 0.5, size[1] as f64 * 0.5);
+                    if x + sw > w as f64 {
+                        x = 0.0;
+                        y += row;
+                        row = 0.0;
+                    }
+                    Image::new()
+                        .src_rect(sprite.src_rect())
+                        .rect([x, y, sw, sh])
+                        .draw(atlas.texture(sprite), &c.draw_state, c.transform, g);
+                    x += sw;
+                    row = f64::max(row, sh);
+                }
+
+                // Atlas entries can also be drawn like standalone textures.
+                let logo = atlas.region(&sprites[0]);
+                Image::new()
+                    .rect([w as f64 - 128.0, h as f64 - 128.0, 128.0, 128.0])
+                    .draw(&logo, &c.draw_state, c.transform, &mut g.regions());
+            });
+            encoder.flush(&mut device);
+        }
+
+        if e.after_render_args().is_some() {
+            device.cleanup();
+        }
+    }
+}
@@ -58,7 +58,7 @@ fn main() {
         factory: factory.clone(),
         encoder: factory.create_command_buffer().into(),
     };
-    let mut rust_logo = Texture::from_image(&mut texture_context, &img, &texture_settings).unwrap();
+    let rust_logo = Texture::from_image(&mut texture_context, &img, &texture_settings).unwrap();
 
     let mut encoder = factory.create_command_buffer().into();
     let draw_size = window.draw_size();
@@ -79,6 +79,8 @@ fn main() {
     while let Some(e) = events.next(&mut window) {
         if let Some(args) = e.render_args() {
             use graphics::*;
+            // Samplers are cached, so the texture is never uploaded again.
+            let sampler = g2d.sampler(&mut factory, &texture_settings);
             g2d.draw(
                 &mut encoder,
                 &output_color,
@@ -101,8 +103,10 @@ fn main() {
                     for (ixv, &ixp) in (0..6).zip(indices_points.iter()) {
                         verts[ixv] = points[ixp];
                     }
-                    g.tri_list_uv(&DrawState::new_alpha(), &[1.0; 4], &rust_logo, |f| {
-                        f(&verts, &uvs)
+                    g.with_sampler(&sampler, |g| {
+                        g.tri_list_uv(&DrawState::new_alpha(), &[1.0; 4], &rust_logo, |f| {
+                            f(&verts, &uvs)
+                        });
                     });
                 },
             );
@@ -112,7 +116,6 @@ fn main() {
         if let Some(Button::Keyboard(Key::U)) = e.press_args() {
             ix_u = (ix_u + 1) % wrap_modes.len();
             texture_settings.set_wrap_u(wrap_modes[ix_u]);
-            rust_logo = Texture::from_image(&mut texture_context, &img, &texture_settings).unwrap();
             println!(
                 "Changed texture wrap mode for u coordinate to: {:?}",
                 wrap_modes[ix_u]
@@ -122,7 +125,6 @@ fn main() {
         if let Some(Button::Keyboard(Key::V)) = e.press_args() {
             ix_v = (ix_v + 1) % wrap_modes.len();
             texture_settings.set_wrap_v(wrap_modes[ix_v]);
-            rust_logo = Texture::from_image(&mut texture_context, &img, &texture_settings).unwrap();
             println!(
                 "Changed texture wrap mode for v coordinate to: {:?}",
                 wrap_modes[ix_v]
@@ -0,0 +1,589 @@
+//! Decoder for ASTC blocks with low dynamic range.
+//!
+//! Every block encodes 4x4 up to 12x12 pixels in 128 bits.
+//! Blocks using HDR color endpoints and reserved encodings
+//! decode to the error color magenta, as LDR decoders do.
+
+// Color of blocks that can not be decoded.
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+// Numbers of weight values selected by block mode, without and with high precision.
+const WEIGHT_LEVELS: [u32; 12] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32];
+
+// Numbers of color values, from which the largest one that fits is used.
+const COLOR_LEVELS: [u32; 17] = [
+    6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256
+];
+
+/// Decodes one block into pixels stored row by row.
+///
+/// The footprint `size` is `[width, height]` and `out` holds its pixels.
+pub(crate) fn decode_block(bytes: &[u8], size: [usize; 2], out: &mut [[u8; 4]]) {
+    let mut b = [0; 16];
+    b.copy_from_slice(&bytes[..16]);
+    if decode(u128::from_le_bytes(b), size, out).is_none() {
+        for pixel in out.iter_mut() {
+            *pixel = ERROR_COLOR;
+        }
+    }
+}
+
+// The weight grid of a block.
+struct BlockMode {
+    grid: [usize; 2],
+    dual_plane: bool,
+    levels: u32,
+}
+
+fn decode(block: u128, [bw, bh]: [usize; 2], out: &mut [[u8; 4]]) -> Option<()> {
+    let mode = bits(block, 0, 11);
+    if mode & 0x1ff == 0x1fc {
+        // Void-extent block of a single color, in half floats when HDR.
+        if mode & 0x200 != 0 {
+            return None;
+        }
+        let color = [
+            (bits(block, 64, 16) >> 8) as u8,
+            (bits(block, 80, 16) >> 8) as u8,
+            (bits(block, 96, 16) >> 8) as u8,
+            (bits(block, 112, 16) >> 8) as u8,
+        ];
+        for pixel in out.iter_mut() {
+            *pixel = color;
+        }
+        return Some(());
+    }
+
+    let mode = block_mode(mode)?;
+    let [gw, gh] = mode.grid;
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weight_count = gw * gh * planes;
+    if gw > bw || gh > bh || weight_count > 64 {
+        return None;
+    }
+    let weight_bits = ise_bits(mode.levels, weight_count as u32);
+    if !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+    let partitions = bits(block, 11, 2) as usize + 1;
+    if partitions == 4 && mode.dual_plane {
+        return None;
+    }
+
+    // Extra configuration is stored below the weights.
+    let mut below_weights = 128 - weight_bits;
+    let mut cems = [0; 4];
+    let color_start = if partitions == 1 {
+        cems[0] = bits(block, 13, 4);
+        17
+    } else {
+        let selector = bits(block, 23, 2);
+        if selector == 0 {
+            let cem = bits(block, 25, 4);
+            for c in &mut cems[..partitions] {
+                *c = cem;
+            }
+        } else {
+            // A class bit per partition, followed by two mode bits per partition.
+            let extra = 3 * partitions as u32 - 4;
+            below_weights -= extra;
+            let field = bits(block, 25, 4) | bits(block, below_weights, extra) << 4;
+            for (i, c) in cems[..partitions].iter_mut().enumerate() {
+                let class = selector - 1 + (field >> i & 1);
+                *c = class << 2 | field >> (partitions + 2 * i) & 3;
+            }
+        }
+        29
+    };
+    // Channel using the second plane of weights.
+    let dual_channel = if mode.dual_plane {
+        below_weights -= 2;
+        Some(bits(block, below_weights, 2) as usize)
+    } else {
+        None
+    };
+
+    if below_weights < color_start {
+        return None;
+    }
+    let color_bits = below_weights - color_start;
+    let value_count: u32 = cems[..partitions].iter().map(|cem| (cem >> 2) * 2 + 2).sum();
+    if value_count > 18 {
+        return None;
+    }
+    let color_levels = *COLOR_LEVELS.iter().rev()
+        .find(|&&levels| ise_bits(levels, value_count) <= color_bits)?;
+    let mut values = [0; 18];
+    let encoded = low_bits(block >> color_start, ise_bits(color_levels, value_count));
+    decode_ise(encoded, color_levels, &mut values[..value_count as usize]);
+    let mut endpoints = [[[0; 4]; 2]; 4];
+    let mut values = values.iter().map(|&v| unquantize_color(v, color_levels));
+    for (e, &cem) in endpoints.iter_mut().zip(cems[..partitions].iter()) {
+        let mut v = [0; 8];
+        for v in &mut v[..((cem >> 2) * 2 + 2) as usize] {
+            *v = values.next()?;
+        }
+        *e = endpoint_colors(cem, &v)?;
+    }
+
+    // Weights are stored from the top of the block down.
+    let mut weights = [0; 64];
+    let encoded = low_bits(block.reverse_bits(), weight_bits);
+    decode_ise(encoded, mode.levels, &mut weights[..weight_count]);
+    for w in &mut weights[..weight_count] {
+        *w = unquantize_weight(*w, mode.levels);
+    }
+
+    let seed = bits(block, 13, 10);
+    let small = bw * bh < 31;
+    let ds = (1024 + bw / 2) / (bw - 1);
+    let dt = (1024 + bh / 2) / (bh - 1);
+    for y in 0..bh {
+        for x in 0..bw {
+            let partition = if partitions > 1 {
+                select_partition(seed, x as u32, y as u32, partitions as u32, small)
+            } else {
+                0
+            };
+            // Bilinear infill of the weight grid.
+            let gs = (ds * x * (gw - 1) + 32) >> 6;
+            let gt = (dt * y * (gh - 1) + 32) >> 6;
+            let (js, fs, jt, ft) = (gs >> 4, (gs & 0xf) as u32, gt >> 4, (gt & 0xf) as u32);
+            let w11 = (fs * ft + 8) >> 4;
+            let factors = [16 + w11 - fs - ft, fs - w11, ft - w11, w11];
+            let weight = |plane: usize| {
+                let texels = [(js, jt), (js + 1, jt), (js, jt + 1), (js + 1, jt + 1)];
+                let sum: u32 = texels.iter().zip(factors.iter()).map(|(&(s, t), f)| {
+                    weights.get((t * gw + s) * planes + plane).map_or(0, |w| w * f)
+                }).sum();
+                (sum + 8) >> 4
+            };
+            let w0 = weight(0);
+            let w1 = if mode.dual_plane { weight(1) } else { w0 };
+            let [e0, e1] = endpoints[partition];
+            let pixel = &mut out[y * bw + x];
+            for c in 0..4 {
+                let w = if dual_channel == Some(c) { w1 } else { w0 };
+                pixel[c] = interpolate(e0[c], e1[c], w);
+            }
+        }
+    }
+    Some(())
+}
+
+// Decodes the weight grid of a block mode, or `None` if reserved.
+fn block_mode(mode: u32) -> Option<BlockMode> {
+    let a = (mode >> 5 & 3) as usize;
+    let (grid, range) = if mode & 3 != 0 {
+        let b = (mode >> 7 & 3) as usize;
+        let grid = match mode >> 2 & 3 {
+            0 => [b + 4, a + 2],
+            1 => [b + 8, a + 2],
+            2 => [a + 2, b + 8],
+            _ if mode & 0x100 == 0 => [a + 2, (b & 1) + 6],
+            _ => [(b & 1) + 2, a + 2],
+        };
+        (grid, mode >> 4 & 1 | (mode & 3) << 1)
+    } else {
+        let b = (mode >> 9 & 3) as usize;
+        let grid = match mode >> 7 & 3 {
+            0 => [12, a + 2],
+            1 => [a + 2, 12],
+            2 => [a + 6, b + 6],
+            _ => match a {
+                0 => [6, 10],
+                1 => [10, 6],
+                _ => return None,
+            },
+        };
+        (grid, mode >> 4 & 1 | (mode >> 2 & 3) << 1)
+    };
+    if range < 2 {
+        return None;
+    }
+    // The grid with 6 to 9 weights per side reuses the precision bits.
+    let (dual_plane, high) = if mode & 3 == 0 && mode >> 7 & 3 == 2 {
+        (false, false)
+    } else {
+        (mode >> 10 & 1 == 1, mode >> 9 & 1 == 1)
+    };
+    Some(BlockMode {
+        grid,
+        dual_plane,
+        levels: WEIGHT_LEVELS[range as usize - 2 + if high { 6 } else { 0 }],
+    })
+}
+
+// Reads `count` bits starting at bit `start`.
+fn bits(block: u128, start: u32, count: u32) -> u32 {
+    low_bits(block >> start, count) as u32
+}
+
+// Keeps the lowest `count` bits.
+fn low_bits(value: u128, count: u32) -> u128 {
+    if count >= 128 { value } else { value & ((1 << count) - 1) }
+}
+
+// Returns the trit or quint base and number of bits encoding a range of values.
+//
+// Every range has 2^n, 3 * 2^n or 5 * 2^n values.
+fn ise_encoding(levels: u32) -> (u32, u32) {
+    let bits = levels.trailing_zeros();
+    (levels >> bits, bits)
+}
+
+// Returns the number of bits of an integer sequence.
+fn ise_bits(levels: u32, count: u32) -> u32 {
+    let (base, bits) = ise_encoding(levels);
+    count * bits + match base {
+        3 => (count * 8).div_ceil(5),
+        5 => (count * 7).div_ceil(3),
+        _ => 0,
+    }
+}
+
+// Reads bits of an integer sequence in order.
+struct BitReader {
+    bits: u128,
+    pos: u32,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = if self.pos < 128 { bits(self.bits, self.pos, count) } else { 0 };
+        self.pos += count;
+        value
+    }
+}
+
+// Decodes an integer sequence, where bits past its end are zero.
+fn decode_ise(encoded: u128, levels: u32, out: &mut [u32]) {
+    let (base, bits) = ise_encoding(levels);
+    let mut r = BitReader { bits: encoded, pos: 0 };
+    match base {
+        3 => {
+            // Five values share 8 bits of trits spread between their bits.
+            for values in out.chunks_mut(5) {
+                let mut m = [0; 5];
+                let mut t = 0;
+                for (i, &(shift, count)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)].iter().enumerate() {
+                    m[i] = r.read(bits);
+                    t |= r.read(count) << shift;
+                }
+                let trits = decode_trits(t);
+                for (i, v) in values.iter_mut().enumerate() {
+                    *v = trits[i] << bits | m[i];
+                }
+            }
+        }
+        5 => {
+            // Three values share 7 bits of quints spread between their bits.
+            for values in out.chunks_mut(3) {
+                let mut m = [0; 3];
+                let mut q = 0;
+                for (i, &(shift, count)) in [(0, 3), (3, 2), (5, 2)].iter().enumerate() {
+                    m[i] = r.read(bits);
+                    q |= r.read(count) << shift;
+                }
+                let quints = decode_quints(q);
+                for (i, v) in values.iter_mut().enumerate() {
+                    *v = quints[i] << bits | m[i];
+                }
+            }
+        }
+        _ => {
+            for v in out {
+                *v = r.read(bits);
+            }
+        }
+    }
+}
+
+// Decodes five trits packed into 8 bits.
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |v: u32, i: u32| v >> i & 1;
+    let (c, t3, t4) = if t >> 2 & 7 == 7 {
+        ((t >> 5 & 7) << 2 | (t & 3), 2, 2)
+    } else if t >> 5 & 3 == 3 {
+        (t & 0x1f, bit(t, 7), 2)
+    } else {
+        (t & 0x1f, t >> 5 & 3, bit(t, 7))
+    };
+    let (t0, t1, t2) = if c & 3 == 3 {
+        (bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1), bit(c, 4), 2)
+    } else if c >> 2 & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        (bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1), c >> 2 & 3, bit(c, 4))
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+// Decodes three quints packed into 7 bits.
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |v: u32, i: u32| v >> i & 1;
+    if q >> 1 & 3 == 3 && q >> 5 & 3 == 0 {
+        let not_q0 = !q & 1;
+        let q2 = bit(q, 0) << 2 | (bit(q, 4) & not_q0) << 1 | (bit(q, 3) & not_q0);
+        return [4, 4, q2];
+    }
+    let (c, q2) = if q >> 1 & 3 == 3 {
+        ((q >> 3 & 3) << 3 | (!q >> 5 & 3) << 1 | (q & 1), 4)
+    } else {
+        (q & 0x1f, q >> 5 & 3)
+    };
+    if c & 7 == 5 {
+        [c >> 3 & 3, 4, q2]
+    } else {
+        [c & 7, c >> 3 & 3, q2]
+    }
+}
+
+// Repeats the bits of a value to fill `to` bits.
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        result = result << bits | value;
+        filled += bits;
+    }
+    result >> (filled - to)
+}
+
+// Unquantizes a color value to 0-255.
+fn unquantize_color(value: u32, levels: u32) -> i32 {
+    let (base, bits) = ise_encoding(levels);
+    if base == 1 {
+        return replicate(value, bits, 8) as i32;
+    }
+    let m = value & ((1 << bits) - 1);
+    let d = value >> bits;
+    let a = if m & 1 == 1 { 0x1ff } else { 0 };
+    let x = m >> 1;
+    let (b, c) = match (base, bits) {
+        (3, 1) => (0, 204),
+        (5, 1) => (0, 113),
+        (3, 2) => (x * 0x116, 93),
+        (5, 2) => (x * 0x10c, 54),
+        (3, 3) => (x << 7 | x << 2 | x, 44),
+        (5, 3) => (x << 7 | x << 1 | x >> 1, 26),
+        (3, 4) => (x << 6 | x, 22),
+        (5, 4) => (x << 6 | x >> 1, 13),
+        (3, 5) => (x << 5 | x >> 2, 11),
+        (5, 5) => (x << 5 | x >> 3, 6),
+        _ => (x << 4 | x >> 4, 5),
+    };
+    let t = (d * c + b) ^ a;
+    ((a & 0x80) | t >> 2) as i32
+}
+
+// Unquantizes a weight to 0-64.
+fn unquantize_weight(value: u32, levels: u32) -> u32 {
+    let (base, bits) = ise_encoding(levels);
+    let w = match (base, bits) {
+        (1, _) => replicate(value, bits, 6),
+        (3, 0) => return [0, 32, 64][value as usize],
+        (5, 0) => return [0, 16, 32, 48, 64][value as usize],
+        _ => {
+            let m = value & ((1 << bits) - 1);
+            let d = value >> bits;
+            let a = if m & 1 == 1 { 0x7f } else { 0 };
+            let x = m >> 1;
+            let (b, c) = match (base, bits) {
+                (3, 1) => (0, 50),
+                (5, 1) => (0, 28),
+                (3, 2) => (x * 0x45, 23),
+                (5, 2) => (x * 0x42, 13),
+                _ => (x << 5 | x, 11),
+            };
+            let t = (d * c + b) ^ a;
+            (a & 0x20) | t >> 2
+        }
+    };
+    if w > 32 { w + 1 } else { w }
+}
+
+// Moves the top bit of `b` into `a`, returning a signed offset and a base.
+fn bit_transfer_signed(b: i32, a: i32) -> (i32, i32) {
+    let base = (a >> 1) | (b & 0x80);
+    let offset = (b >> 1) & 0x3f;
+    (if offset & 0x20 != 0 { offset - 0x40 } else { offset }, base)
+}
+
+// Moves some blue into red and green.
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+// Decodes the two endpoint colors of a color endpoint mode.
+fn endpoint_colors(cem: u32, v: &[i32; 8]) -> Option<[[i32; 4]; 2]> {
+    let e = match cem {
+        // Luminance.
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = (l0 + (v[1] & 0x3f)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        // Luminance with alpha.
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (dl, l) = bit_transfer_signed(v[1], v[0]);
+            let (da, a) = bit_transfer_signed(v[3], v[2]);
+            [[l, l, l, a], [l + dl, l + dl, l + dl, a + da]]
+        }
+        // RGB scaled, with alpha.
+        6 | 10 => {
+            let (a0, a1) = if cem == 6 { (255, 255) } else { (v[4], v[5]) };
+            let scale = |c: i32| (c * v[3]) >> 8;
+            [[scale(v[0]), scale(v[1]), scale(v[2]), a0], [v[0], v[1], v[2], a1]]
+        }
+        // RGB and RGBA.
+        8 | 12 => {
+            let (a0, a1) = if cem == 8 { (255, 255) } else { (v[6], v[7]) };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]]
+            } else {
+                [blue_contract([v[1], v[3], v[5], a1]), blue_contract([v[0], v[2], v[4], a0])]
+            }
+        }
+        9 | 13 => {
+            let (dr, r) = bit_transfer_signed(v[1], v[0]);
+            let (dg, g) = bit_transfer_signed(v[3], v[2]);
+            let (db, b) = bit_transfer_signed(v[5], v[4]);
+            let (da, a) = if cem == 9 { (0, 255) } else { bit_transfer_signed(v[7], v[6]) };
+            if dr + dg + db >= 0 {
+                [[r, g, b, a], [r + dr, g + dg, b + db, a + da]]
+            } else {
+                [blue_contract([r + dr, g + dg, b + db, a + da]), blue_contract([r, g, b, a])]
+            }
+        }
+        // HDR modes.
+        _ => return None,
+    };
+    let clamp = |c: [i32; 4]| [
+        c[0].clamp(0, 255), c[1].clamp(0, 255), c[2].clamp(0, 255), c[3].clamp(0, 255)
+    ];
+    Some([clamp(e[0]), clamp(e[1])])
+}
+
+// Interpolates a channel between endpoints by a weight of 0-64.
+fn interpolate(e0: i32, e1: i32, weight: u32) -> u8 {
+    let w = weight as i32;
+    let (c0, c1) = (e0 << 8 | e0, e1 << 8 | e1);
+    ((c0 * (64 - w) + c1 * w + 32) >> 6 >> 8) as u8
+}
+
+// Selects the partition of a pixel by hashing its position with the seed.
+fn select_partition(seed: u32, x: u32, y: u32, partitions: u32, small: bool) -> usize {
+    let (x, y) = if small { (x << 1, y << 1) } else { (x, y) };
+    let seed = seed + (partitions - 1) * 1024;
+    let rnum = hash52(seed);
+    // Seeds that weigh the depth of 3D blocks are left out.
+    let mut s = [0; 8];
+    for (i, s) in s.iter_mut().enumerate() {
+        let v = rnum >> (4 * i) & 0xf;
+        *s = v * v;
+    }
+    let (sh1, sh2) = if seed & 1 != 0 {
+        (if seed & 2 != 0 { 4 } else { 5 }, if partitions == 3 { 6 } else { 5 })
+    } else {
+        (if partitions == 3 { 6 } else { 5 }, if seed & 2 != 0 { 4 } else { 5 })
+    };
+    for (i, s) in s.iter_mut().enumerate() {
+        *s >>= if i % 2 == 0 { sh1 } else { sh2 };
+    }
+    let a = (s[0] * x + s[1] * y + (rnum >> 14)) & 0x3f;
+    let b = (s[2] * x + s[3] * y + (rnum >> 10)) & 0x3f;
+    let c = if partitions < 3 { 0 } else { (s[4] * x + s[5] * y + (rnum >> 6)) & 0x3f };
+    let d = if partitions < 4 { 0 } else { (s[6] * x + s[7] * y + (rnum >> 2)) & 0x3f };
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+// Hashes the partition seed.
+fn hash52(mut p: u32) -> u32 {
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trits_and_quints_cover_all_values() {
+        let mut trits: Vec<[u32; 5]> = (0..256).map(decode_trits).collect();
+        trits.sort();
+        trits.dedup();
+        assert_eq!(trits.len(), 243);
+        let mut quints: Vec<[u32; 3]> = (0..128).map(decode_quints).collect();
+        quints.sort();
+        quints.dedup();
+        assert_eq!(quints.len(), 125);
+    }
+
+    #[test]
+    fn unquantize_weights() {
+        let weights: Vec<u32> = (0..12).map(|v| unquantize_weight(v, 12)).collect();
+        assert_eq!(weights, [0, 64, 17, 47, 5, 59, 23, 41, 11, 53, 28, 36]);
+        assert_eq!(unquantize_weight(3, 4), 64);
+    }
+
+    #[test]
+    fn void_extent() {
+        // Extent coordinates of all ones mark a constant color block.
+        let mut block: u128 = 0xdfc | ((1 << 52) - 1) << 12;
+        block |= 0xff00 << 64 | 0x8000 << 80 | 0xffff << 112;
+        let mut out = [[0; 4]; 36];
+        decode_block(&block.to_le_bytes(), [6, 6], &mut out);
+        assert!(out.iter().all(|&p| p == [255, 128, 0, 255]));
+    }
+
+    #[test]
+    fn rgb_direct() {
+        // A 4x4 grid of 2 bit weights, one partition with RGB endpoints of 8 bits.
+        let mut block: u128 = 66 | 8 << 13;
+        for (i, &v) in [0, 255, 0, 255, 0, 255].iter().enumerate() {
+            block |= (v as u128) << (17 + 8 * i);
+        }
+        let weights = [0, 1, 2, 3, 3, 2, 1, 0, 0, 0, 3, 3, 1, 1, 2, 2];
+        for (i, &w) in weights.iter().enumerate() {
+            for bit in 0..2 {
+                if w >> bit & 1 == 1 {
+                    block |= 1 << (127 - (2 * i + bit));
+                }
+            }
+        }
+        let mut out = [[0; 4]; 16];
+        decode_block(&block.to_le_bytes(), [4, 4], &mut out);
+        let expected = [0, 84, 171, 255];
+        for (pixel, &w) in out.iter().zip(weights.iter()) {
+            let c = expected[w];
+            assert_eq!(*pixel, [c, c, c, 255]);
+        }
+    }
+
+    #[test]
+    fn reserved_block_is_magenta() {
+        let mut out = [[0; 4]; 16];
+        decode_block(&[0; 16], [4, 4], &mut out);
+        assert!(out.iter().all(|&p| p == ERROR_COLOR));
+    }
+}
@@ -0,0 +1,95 @@
+//! Texture atlases.
+
+use gfx;
+
+use packer::ShelfPacker;
+use upload::UploadQueue;
+use {CreateTexture, Error, Format, Texture, TextureContext, TextureSettings};
+
+/// Atlas textures with room for tiles, created by need.
+///
+/// Pages are created through a `TextureContext`,
+/// while RGBA tiles are queued until written with `flush_uploads`.
+pub(crate) struct AtlasPages<R: gfx::Resources> {
+    size: [u32; 2],
+    settings: TextureSettings,
+    pages: Vec<(Texture<R>, ShelfPacker)>,
+    queue: UploadQueue<R>,
+}
+
+impl<R: gfx::Resources> AtlasPages<R> {
+    /// Creates atlas pages of a minimum size.
+    pub(crate) fn new(size: [u32; 2], settings: TextureSettings) -> AtlasPages<R> {
+        AtlasPages {
+            size,
+            settings,
+            pages: vec![],
+            queue: UploadQueue::new(),
+        }
+    }
+
+    /// Sets the minimum size of new pages.
+    pub(crate) fn set_size(&mut self, size: [u32; 2]) {
+        self.size = size;
+    }
+
+    /// Returns the texture of a page.
+    pub(crate) fn texture(&self, page: usize) -> &Texture<R> {
+        &self.pages[page].0
+    }
+
+    /// Returns the textures of all pages.
+    pub(crate) fn textures(&self) -> impl Iterator<Item = &Texture<R>> {
+        self.pages.iter().map(|p| &p.0)
+    }
+
+    /// Queues an RGBA tile and returns its page and offset.
+    ///
+    /// Creates a new page when there is no room left in the last one.
+    /// New pages are created through the factory of the context.
+    pub(crate) fn add<F, C>(
+        &mut self,
+        context: &mut TextureContext<F, R, C>,
+        size: [u32; 2],
+        memory: &[u8]
+    ) -> Result<(usize, [u32; 2]), Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        let (page, offset) = self.allocate(context, size)?;
+        self.queue.push(&self.pages[page].0.surface, offset, size, memory);
+        Ok((page, offset))
+    }
+
+    /// Writes queued tiles through the encoder used when drawing.
+    pub(crate) fn flush_uploads<C>(&mut self, encoder: &mut gfx::Encoder<R, C>)
+        where C: gfx::CommandBuffer<R>
+    {
+        self.queue.submit(encoder);
+    }
+
+    // Finds room for a tile, creating a new page if needed.
+    fn allocate<F, C>(
+        &mut self,
+        context: &mut TextureContext<F, R, C>,
+        size: [u32; 2]
+    ) -> Result<(usize, [u32; 2]), Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        if let Some(&mut (_, ref mut packer)) = self.pages.last_mut() {
+            if let Some(offset) = packer.pack(size) {
+                return Ok((self.pages.len() - 1, offset));
+            }
+        }
+
+        let page_size = [size[0].max(self.size[0]), size[1].max(self.size[1])];
+        let memory = vec![0; (page_size[0] * page_size[1] * 4) as usize];
+        let texture = CreateTexture::create(context, Format::Rgba8,
+                                            &memory, page_size, &self.settings)?;
+        let mut packer = ShelfPacker::new(page_size);
+        let offset = packer.pack(size).expect("Page is large enough for tile");
+        self.pages.push((texture, packer));
+        Ok((self.pages.len() - 1, offset))
+    }
+}
@@ -1,18 +1,32 @@
 extern crate gfx;
 
+use std::collections::HashMap;
+use std::error::Error;
+use std::mem::{self, size_of};
+use std::time::Instant;
 use graphics::{ Context, DrawState, Graphics, Viewport };
 use graphics::BACK_END_MAX_VERTEX_COUNT as BUFFER_SIZE;
 use graphics::draw_state;
 use graphics::color::gamma_srgb_to_linear;
-use Texture;
+use graphics::math::Matrix2d;
+use {Texture, TextureSettings};
+use render_target::RenderTarget;
+use sampler::sampler_info;
+use debug::{outline, DebugOverlay, DebugState};
+use trace::{Trace, TraceCall, TraceChunk, TraceRecorder};
+use stats::{FrameStats, GpuTimes, GpuTimesError, Pipeline, StatsRecorder};
+use texture_array::TextureArray;
+use texture_region::RegionGraphics;
+use edge_aa::Fringe;
 use gfx::format::{DepthStencil, Srgba8};
 use gfx::pso::PipelineState;
 use shader_version::{ OpenGL, Shaders };
 use shader_version::glsl::GLSL;
 
 // The number of chunks to fill up before rendering.
-// Amount of memory used: `BUFFER_SIZE * CHUNKS * 4 * (2 + 4)`
-// `4` for bytes per f32, and `2 + 4` for position and color.
+// Amount of memory used: `BUFFER_SIZE * CHUNKS * 4 * (2 + 4 + 2)`
+// `4` for bytes per f32, and `2 + 4 + 2` for position, color and texture coordinates.
+// When texture arrays are drawn, one more f32 is used for the layer.
 const CHUNKS: usize = 100;
 
 gfx_defines! {
@@ -27,6 +41,10 @@ gfx_defines! {
     vertex TexCoordsFormat {
         uv: [f32; 2] = "uv",
     }
+
+    vertex LayerFormat {
+        layer: f32 = "layer",
+    }
 }
 
 gfx_pipeline_base!( pipe_colored {
@@ -60,6 +78,91 @@ gfx_pipeline_base!( pipe_textured_color {
     scissor: gfx::Scissor,
 });
 
+gfx_pipeline_base!( pipe_textured_array {
+    pos: gfx::VertexBuffer<PositionFormat>,
+    uv: gfx::VertexBuffer<TexCoordsFormat>,
+    layer: gfx::VertexBuffer<LayerFormat>,
+    color: gfx::Global<[f32; 4]>,
+    texture: gfx::TextureSampler<[f32; 4]>,
+    blend_target: gfx::BlendTarget<gfx::format::Srgba8>,
+    stencil_target: gfx::StencilTarget<gfx::format::DepthStencil>,
+    blend_ref: gfx::BlendRef,
+    scissor: gfx::Scissor,
+});
+
+gfx_pipeline_base!( pipe_textured_channels {
+    pos: gfx::VertexBuffer<PositionFormat>,
+    uv: gfx::VertexBuffer<TexCoordsFormat>,
+    color: gfx::Global<[f32; 4]>,
+    luminance: gfx::Global<f32>,
+    texture: gfx::TextureSampler<[f32; 4]>,
+    blend_target: gfx::BlendTarget<gfx::format::Srgba8>,
+    stencil_target: gfx::StencilTarget<gfx::format::DepthStencil>,
+    blend_ref: gfx::BlendRef,
+    scissor: gfx::Scissor,
+});
+
+gfx_pipeline_base!( pipe_textured_color_channels {
+    pos: gfx::VertexBuffer<PositionFormat>,
+    uv: gfx::VertexBuffer<TexCoordsFormat>,
+    color: gfx::VertexBuffer<ColorFormat>,
+    luminance: gfx::Global<f32>,
+    texture: gfx::TextureSampler<[f32; 4]>,
+    blend_target: gfx::BlendTarget<gfx::format::Srgba8>,
+    stencil_target: gfx::StencilTarget<gfx::format::DepthStencil>,
+    blend_ref: gfx::BlendRef,
+    scissor: gfx::Scissor,
+});
+
+gfx_pipeline_base!( pipe_resolve {
+    pos: gfx::VertexBuffer<PositionFormat>,
+    texture: gfx::ShaderResource<[f32; 4]>,
+    samples: gfx::Global<i32>,
+    target: gfx::RenderTarget<gfx::format::Srgba8>,
+});
+
+// A triangle covering the whole viewport.
+const FULLSCREEN_TRIANGLE: [[f32; 2]; 3] = [[-1.0, -1.0], [3.0, -1.0], [-1.0, 3.0]];
+
+// Selects the program used for textured rendering with a uniform color.
+#[derive(Clone, Copy, PartialEq)]
+enum TexturedProgram {
+    // Multiplies the texture color with the uniform color.
+    Standard,
+    // Reads a signed distance field from the texture alpha channel.
+    Sdf,
+    // Samples a layer of a texture array given per vertex.
+    Array,
+    // Reads alpha from the red channel of R8 textures.
+    Alpha,
+    // Reads luminance and alpha from the red and green channels of R8_G8 textures.
+    LuminanceAlpha,
+}
+
+// The state shared by batched textured triangles.
+#[derive(PartialEq)]
+struct TexturedBatch<R: gfx::Resources> {
+    program: TexturedProgram,
+    draw_state: DrawState,
+    // Linear color.
+    color: [f32; 4],
+    view: gfx::handle::ShaderResourceView<R, [f32; 4]>,
+    sampler: gfx::handle::Sampler<R>,
+}
+
+// The pipelines and layer buffer used for texture arrays.
+struct ArrayPipeline<R: gfx::Resources> {
+    pso: PsoStencil<PipelineState<R, pipe_textured_array::Meta>>,
+    buffer_layer: gfx::handle::Buffer<R, LayerFormat>,
+}
+
+// The pipelines and index buffer used for drawing triangles as wireframe.
+struct WireframePipeline<R: gfx::Resources> {
+    pso: PsoStencil<PipelineState<R, pipe_colored::Meta>>,
+    // Indices of the three edges of each triangle in the vertex buffers.
+    index: gfx::handle::Buffer<R, u32>,
+}
+
 // Stores one PSO per blend setting.
 struct PsoBlend<T> {
     alpha: T,
@@ -85,6 +188,9 @@ impl<T> PsoBlend<T> {
     }
 }
 
+// The number of pipeline states in a `PsoStencil`, one per stencil and blend setting.
+const PSO_STENCIL_LEN: usize = 5 * 6;
+
 // Stores one `PsoBlend` per clip setting.
 struct PsoStencil<T> {
     none: PsoBlend<T>,
@@ -95,18 +201,19 @@ struct PsoStencil<T> {
 }
 
 impl<T> PsoStencil<T> {
-    fn new<Fact, F>(factory: &mut Fact, f: F) -> PsoStencil<T>
+    fn new<Fact, F, E>(factory: &mut Fact, name: &str, f: F) -> Result<PsoStencil<T>, E>
         where F: Fn(
             &mut Fact,
             gfx::state::Blend,
             gfx::state::Stencil,
             gfx::state::ColorMask
-        ) -> T
+        ) -> Result<T, E>
     {
         use gfx::state::{Blend, BlendChannel, Comparison, Equation, Factor,
             Stencil, StencilOp};
         use gfx::preset::blend;
 
+        let start = Instant::now();
         let stencil = Stencil::new(Comparison::Always, 0,
             (StencilOp::Keep, StencilOp::Keep, StencilOp::Keep));
         let stencil_clip = Stencil::new(Comparison::Never, 255,
@@ -151,48 +258,50 @@ impl<T> PsoStencil<T> {
                     },
                 };
 
-        PsoStencil {
+        let pso = PsoStencil {
             none: PsoBlend {
-                alpha: f(factory, blend::ALPHA, stencil, mask_all),
-                add: f(factory, blend::ADD, stencil, mask_all),
-                multiply: f(factory, blend::MULTIPLY, stencil, mask_all),
-                invert: f(factory, blend::INVERT, stencil, mask_all),
-                lighter: f(factory, BLEND_LIGHTER, stencil, mask_all),
-                none: f(factory, no_blend, stencil, mask_all),
+                alpha: f(factory, blend::ALPHA, stencil, mask_all)?,
+                add: f(factory, blend::ADD, stencil, mask_all)?,
+                multiply: f(factory, blend::MULTIPLY, stencil, mask_all)?,
+                invert: f(factory, blend::INVERT, stencil, mask_all)?,
+                lighter: f(factory, BLEND_LIGHTER, stencil, mask_all)?,
+                none: f(factory, no_blend, stencil, mask_all)?,
             },
             clip: PsoBlend {
-                alpha: f(factory, blend::ALPHA, stencil_clip, mask_none),
-                add: f(factory, blend::ADD, stencil_clip, mask_none),
-                multiply: f(factory, blend::MULTIPLY, stencil_clip, mask_none),
-                invert: f(factory, blend::INVERT, stencil_clip, mask_none),
-                lighter: f(factory, BLEND_LIGHTER, stencil_clip, mask_none),
-                none: f(factory, no_blend, stencil_clip, mask_none),
+                alpha: f(factory, blend::ALPHA, stencil_clip, mask_none)?,
+                add: f(factory, blend::ADD, stencil_clip, mask_none)?,
+                multiply: f(factory, blend::MULTIPLY, stencil_clip, mask_none)?,
+                invert: f(factory, blend::INVERT, stencil_clip, mask_none)?,
+                lighter: f(factory, BLEND_LIGHTER, stencil_clip, mask_none)?,
+                none: f(factory, no_blend, stencil_clip, mask_none)?,
             },
             inside: PsoBlend {
-                alpha: f(factory, blend::ALPHA, stencil_inside, mask_all),
-                add: f(factory, blend::ADD, stencil_inside, mask_all),
-                multiply: f(factory, blend::MULTIPLY, stencil_inside, mask_all),
-                invert: f(factory, blend::INVERT, stencil_inside, mask_all),
-                lighter: f(factory, BLEND_LIGHTER, stencil_inside, mask_all),
-                none: f(factory, no_blend, stencil_inside, mask_all),
+                alpha: f(factory, blend::ALPHA, stencil_inside, mask_all)?,
+                add: f(factory, blend::ADD, stencil_inside, mask_all)?,
+                multiply: f(factory, blend::MULTIPLY, stencil_inside, mask_all)?,
+                invert: f(factory, blend::INVERT, stencil_inside, mask_all)?,
+                lighter: f(factory, BLEND_LIGHTER, stencil_inside, mask_all)?,
+                none: f(factory, no_blend, stencil_inside, mask_all)?,
             },
             outside: PsoBlend {
-                alpha: f(factory, blend::ALPHA, stencil_outside, mask_all),
-                add: f(factory, blend::ADD, stencil_outside, mask_all),
-                multiply: f(factory, blend::MULTIPLY, stencil_outside, mask_all),
-                invert: f(factory, blend::INVERT, stencil_outside, mask_all),
-                lighter: f(factory, BLEND_LIGHTER, stencil_outside, mask_all),
-                none: f(factory, no_blend, stencil_outside, mask_all),
+                alpha: f(factory, blend::ALPHA, stencil_outside, mask_all)?,
+                add: f(factory, blend::ADD, stencil_outside, mask_all)?,
+                multiply: f(factory, blend::MULTIPLY, stencil_outside, mask_all)?,
+                invert: f(factory, blend::INVERT, stencil_outside, mask_all)?,
+                lighter: f(factory, BLEND_LIGHTER, stencil_outside, mask_all)?,
+                none: f(factory, no_blend, stencil_outside, mask_all)?,
             },
             increment: PsoBlend {
-                alpha: f(factory, blend::ALPHA, stencil_increment, mask_all),
-                add: f(factory, blend::ADD, stencil_increment, mask_all),
-                multiply: f(factory, blend::MULTIPLY, stencil_increment, mask_all),
-                invert: f(factory, blend::INVERT, stencil_increment, mask_all),
-                lighter: f(factory, BLEND_LIGHTER, stencil_increment, mask_all),
-                none: f(factory, no_blend, stencil_increment, mask_all),
+                alpha: f(factory, blend::ALPHA, stencil_increment, mask_all)?,
+                add: f(factory, blend::ADD, stencil_increment, mask_all)?,
+                multiply: f(factory, blend::MULTIPLY, stencil_increment, mask_all)?,
+                invert: f(factory, blend::INVERT, stencil_increment, mask_all)?,
+                lighter: f(factory, BLEND_LIGHTER, stencil_increment, mask_all)?,
+                none: f(factory, no_blend, stencil_increment, mask_all)?,
             },
-        }
+        };
+        debug!("Built {} `{}` pipeline states in {:?}", PSO_STENCIL_LEN, name, start.elapsed());
+        Ok(pso)
     }
 
     // Returns a PSO and stencil reference given a stencil and blend setting.
@@ -213,24 +322,398 @@ impl<T> PsoStencil<T> {
     }
 }
 
+// The error of building pipeline states.
+type PipelineError = gfx::PipelineStateError<String>;
+
+// The error of building the pipelines that only some draw calls need.
+type OptionalError = Box<dyn Error>;
+
+// Links a program from the shaders of a GLSL version.
+fn link_program<R, F>(
+    factory: &mut F,
+    glsl: GLSL,
+    vertex: &Shaders<GLSL, [u8]>,
+    fragment: &Shaders<GLSL, [u8]>
+) -> Result<gfx::handle::Program<R>, PipelineError>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>
+{
+    use gfx::shade::ProgramError;
+    use gfx::shade::core::CreateShaderError;
+    use gfx::traits::FactoryExt;
+
+    match (vertex.get(glsl), fragment.get(glsl)) {
+        (Some(vertex), Some(fragment)) => Ok(factory.link_program(vertex, fragment)?),
+        _ => Err(PipelineError::Program(
+            ProgramError::Vertex(CreateShaderError::ModelNotSupported))),
+    }
+}
+
+// Builds the pipelines for signed distance fields.
+fn sdf_pipelines<R, F>(factory: &mut F, glsl: GLSL)
+    -> Result<PsoStencil<PipelineState<R, pipe_textured::Meta>>, OptionalError>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>
+{
+    use gfx::Primitive;
+    use gfx::state::Rasterizer;
+    use gfx::traits::FactoryExt;
+    use glsl::sdf;
+
+    let program = link_program(factory, glsl,
+        Shaders::new()
+            .set(GLSL::V1_20, sdf::VERTEX_GLSL_120)
+            .set(GLSL::V1_50, sdf::VERTEX_GLSL_150_CORE),
+        Shaders::new()
+            .set(GLSL::V1_20, sdf::FRAGMENT_GLSL_120)
+            .set(GLSL::V1_50, sdf::FRAGMENT_GLSL_150_CORE)
+    )?;
+    Ok(PsoStencil::new(factory, "sdf", |factory, blend, stencil, color_mask| {
+        factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer::new_fill(),
+            pipe_textured::Init {
+                pos: (),
+                uv: (),
+                color: "color",
+                texture: "s_texture",
+                blend_target: ("o_Color", color_mask, blend),
+                stencil_target: stencil,
+                blend_ref: (),
+                scissor: (),
+            }
+        ).map_err(PipelineError::from)
+    })?)
+}
+
+// Builds the pipelines for single and two channel textures.
+fn channels_pipelines<R, F>(factory: &mut F, glsl: GLSL)
+    -> Result<PsoStencil<PipelineState<R, pipe_textured_channels::Meta>>, OptionalError>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>
+{
+    use gfx::Primitive;
+    use gfx::state::Rasterizer;
+    use gfx::traits::FactoryExt;
+    use glsl::channels;
+
+    let program = link_program(factory, glsl,
+        Shaders::new()
+            .set(GLSL::V1_20, channels::VERTEX_GLSL_120)
+            .set(GLSL::V1_50, channels::VERTEX_GLSL_150_CORE),
+        Shaders::new()
+            .set(GLSL::V1_20, channels::FRAGMENT_GLSL_120)
+            .set(GLSL::V1_50, channels::FRAGMENT_GLSL_150_CORE)
+    )?;
+    Ok(PsoStencil::new(factory, "channels", |factory, blend, stencil, color_mask| {
+        factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer::new_fill(),
+            pipe_textured_channels::Init {
+                pos: (),
+                uv: (),
+                color: "color",
+                luminance: "luminance",
+                texture: "s_texture",
+                blend_target: ("o_Color", color_mask, blend),
+                stencil_target: stencil,
+                blend_ref: (),
+                scissor: (),
+            }
+        ).map_err(PipelineError::from)
+    })?)
+}
+
+// Builds the pipelines for single and two channel textures with a color per vertex.
+fn channels_color_pipelines<R, F>(factory: &mut F, glsl: GLSL)
+    -> Result<PsoStencil<PipelineState<R, pipe_textured_color_channels::Meta>>, OptionalError>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>
+{
+    use gfx::Primitive;
+    use gfx::state::Rasterizer;
+    use gfx::traits::FactoryExt;
+    use glsl::channels_color;
+
+    let program = link_program(factory, glsl,
+        Shaders::new()
+            .set(GLSL::V1_20, channels_color::VERTEX_GLSL_120)
+            .set(GLSL::V1_50, channels_color::VERTEX_GLSL_150_CORE),
+        Shaders::new()
+            .set(GLSL::V1_20, channels_color::FRAGMENT_GLSL_120)
+            .set(GLSL::V1_50, channels_color::FRAGMENT_GLSL_150_CORE)
+    )?;
+    Ok(PsoStencil::new(factory, "channels_color", |factory, blend, stencil, color_mask| {
+        factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer::new_fill(),
+            pipe_textured_color_channels::Init {
+                pos: (),
+                uv: (),
+                color: (),
+                luminance: "luminance",
+                texture: "s_texture",
+                blend_target: ("o_Color", color_mask, blend),
+                stencil_target: stencil,
+                blend_ref: (),
+                scissor: (),
+            }
+        ).map_err(PipelineError::from)
+    })?)
+}
+
+// Builds the pipelines and layer buffer for texture arrays.
+fn array_pipelines<R, F>(factory: &mut F, glsl: GLSL) -> Result<ArrayPipeline<R>, OptionalError>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>
+{
+    use gfx::Primitive;
+    use gfx::state::Rasterizer;
+    use gfx::traits::FactoryExt;
+    use glsl::array;
+
+    let program = link_program(factory, glsl,
+        Shaders::new().set(GLSL::V1_50, array::VERTEX_GLSL_150_CORE),
+        Shaders::new().set(GLSL::V1_50, array::FRAGMENT_GLSL_150_CORE)
+    )?;
+    let pso = PsoStencil::new(factory, "array", |factory, blend, stencil, color_mask| {
+        factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer::new_fill(),
+            pipe_textured_array::Init {
+                pos: (),
+                uv: (),
+                layer: (),
+                color: "color",
+                texture: "s_texture",
+                blend_target: ("o_Color", color_mask, blend),
+                stencil_target: stencil,
+                blend_ref: (),
+                scissor: (),
+            }
+        ).map_err(PipelineError::from)
+    })?;
+    let buffer_layer = factory.create_buffer(
+        BUFFER_SIZE * CHUNKS,
+        gfx::buffer::Role::Vertex,
+        gfx::memory::Usage::Dynamic,
+        gfx::memory::Bind::empty()
+    )?;
+    Ok(ArrayPipeline {
+        pso,
+        buffer_layer,
+    })
+}
+
+// Builds the line pipelines and edge indices for drawing wireframe.
+fn wireframe_pipelines<R, F>(factory: &mut F, glsl: GLSL)
+    -> Result<WireframePipeline<R>, OptionalError>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>
+{
+    use gfx::Primitive;
+    use gfx::state::Rasterizer;
+    use gfx::traits::FactoryExt;
+    use shaders::colored;
+
+    let program = link_program(factory, glsl,
+        Shaders::new()
+            .set(GLSL::V1_20, colored::VERTEX_GLSL_120)
+            .set(GLSL::V1_50, colored::VERTEX_GLSL_150_CORE),
+        Shaders::new()
+            .set(GLSL::V1_20, colored::FRAGMENT_GLSL_120)
+            .set(GLSL::V1_50, colored::FRAGMENT_GLSL_150_CORE)
+    )?;
+    // Draws lines instead of changing the polygon mode, which OpenGL ES lacks.
+    let pso = PsoStencil::new(factory, "wireframe", |factory, blend, stencil, color_mask| {
+        factory.create_pipeline_from_program(
+            &program,
+            Primitive::LineList,
+            Rasterizer::new_fill(),
+            pipe_colored::Init {
+                pos: (),
+                color: (),
+                blend_target: ("o_Color", color_mask, blend),
+                stencil_target: stencil,
+                blend_ref: (),
+                scissor: (),
+            }
+        ).map_err(PipelineError::from)
+    })?;
+    let triangles = (BUFFER_SIZE * CHUNKS / 3) as u32;
+    let mut indices = Vec::with_capacity(triangles as usize * 6);
+    for i in 0..triangles {
+        let (a, b, c) = (3 * i, 3 * i + 1, 3 * i + 2);
+        indices.extend_from_slice(&[a, b, b, c, c, a]);
+    }
+    let index = factory.create_buffer_immutable(
+        &indices,
+        gfx::buffer::Role::Index,
+        gfx::memory::Bind::empty()
+    )?;
+    Ok(WireframePipeline {
+        pso,
+        index,
+    })
+}
+
+// Pipelines that only some draw calls need.
+//
+// Building every pipeline takes a while at startup,
+// so these are built by the `enable_*` methods of `Gfx2d`.
+enum Optional<T> {
+    Disabled,
+    // Draw calls were skipped while disabled, which is only logged once.
+    Skipped,
+    Enabled(T),
+}
+
+impl<T> Optional<T> {
+    // Returns the pipelines, logging the first draw call skipped without them.
+    fn require(&mut self, enable: &str) -> Option<&mut T> {
+        if let Optional::Disabled = *self {
+            error!("Skipping draw calls until `Gfx2d::{}` is called", enable);
+            *self = Optional::Skipped;
+        }
+        self.get_mut()
+    }
+
+    // Returns the pipelines if they were built.
+    fn get_mut(&mut self) -> Option<&mut T> {
+        match *self {
+            Optional::Enabled(ref mut val) => Some(val),
+            Optional::Disabled | Optional::Skipped => None,
+        }
+    }
+}
+
+// Closures of the `Graphics` triangle list methods as trait objects.
+type TriListFn<'a> = dyn FnMut(&mut dyn FnMut(&[[f32; 2]])) + 'a;
+type TriListCFn<'a> = dyn FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]])) + 'a;
+type TriListUvFn<'a> = dyn FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]])) + 'a;
+type TriListUvCFn<'a> = dyn FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]])) + 'a;
+
+// Converts a scissor rectangle `[x, y, w, h]` into a gfx scissor.
+fn scissor_rect(scissor: Option<[u32; 4]>) -> ::draw_state::target::Rect {
+    use draw_state::target::Rect;
+
+    match scissor {
+        None => Rect { x: 0, y: 0, w: u16::MAX, h: u16::MAX },
+        Some(r) => Rect { x: r[0] as u16, y: r[1] as u16,
+            w: r[2] as u16, h: r[3] as u16 }
+    }
+}
+
+// Writes vertices to a buffer, logging failures instead of panicking.
+//
+// Returns false if nothing was written, so the caller can skip drawing.
+fn write_buffer<R, C, T>(
+    encoder: &mut gfx::Encoder<R, C>,
+    buffer: &gfx::handle::Buffer<R, T>,
+    data: &[T],
+    offset: usize
+) -> bool
+    where R: gfx::Resources,
+          C: gfx::CommandBuffer<R>,
+          T: gfx::memory::Pod
+{
+    match encoder.update_buffer(buffer, data, offset) {
+        Ok(()) => true,
+        Err(err) => {
+            error!("Could not write {} vertices at offset {} to a buffer of {}: {:?}",
+                data.len(), offset, buffer.len(), err);
+            false
+        }
+    }
+}
+
+// Returns true if edge anti-aliasing can be used with a draw state.
+//
+// The fringe relies on alpha blending and must not grow clipping shapes.
+fn supports_edge_aa(draw_state: &DrawState) -> bool {
+    use graphics::draw_state::{Blend, Stencil};
+
+    matches!((draw_state.blend, draw_state.stencil),
+        (Some(Blend::Alpha), None) |
+        (Some(Blend::Alpha), Some(Stencil::Inside(_))) |
+        (Some(Blend::Alpha), Some(Stencil::Outside(_))))
+}
+
+// Computes the intersection of two scissor rectangles `[x, y, w, h]`.
+//
+// Returns a zero sized rectangle when there is no overlap.
+fn intersect_scissor(a: [u32; 4], b: [u32; 4]) -> [u32; 4] {
+    let x0 = a[0].max(b[0]);
+    let y0 = a[1].max(b[1]);
+    let x1 = a[0].saturating_add(a[2]).min(b[0].saturating_add(b[2]));
+    let y1 = a[1].saturating_add(a[3]).min(b[1].saturating_add(b[3]));
+    if x1 <= x0 || y1 <= y0 {
+        [x0, y0, 0, 0]
+    } else {
+        [x0, y0, x1 - x0, y1 - y0]
+    }
+}
+
 /// The data used for drawing 2D graphics.
 ///
 /// Stores buffers and PSO objects needed for rendering 2D graphics.
+///
+/// Glyph caches and texture atlases queue new tiles until they are written
+/// through `GfxGraphics`, e.g. with `AtlasGlyphCache::flush_uploads`.
 pub struct Gfx2d<R: gfx::Resources> {
     // The offset in vertices for colored rendering.
     colored_offset: usize,
     // The current draw state for colored rendering.
     colored_draw_state: DrawState,
+    // The offset in vertices for textured rendering.
+    textured_offset: usize,
+    // The current batch for textured rendering.
+    textured_batch: Option<TexturedBatch<R>>,
     buffer_pos: gfx::handle::Buffer<R, PositionFormat>,
     buffer_color: gfx::handle::Buffer<R, ColorFormat>,
     buffer_uv: gfx::handle::Buffer<R, TexCoordsFormat>,
     colored: PsoStencil<PipelineState<R, pipe_colored::Meta>>,
     textured: PsoStencil<PipelineState<R, pipe_textured::Meta>>,
     textured_color: PsoStencil<PipelineState<R, pipe_textured_color::Meta>>,
+    sdf: Optional<PsoStencil<PipelineState<R, pipe_textured::Meta>>>,
+    channels: Optional<PsoStencil<PipelineState<R, pipe_textured_channels::Meta>>>,
+    channels_color: Optional<PsoStencil<PipelineState<R, pipe_textured_color_channels::Meta>>>,
+    wireframe: Optional<WireframePipeline<R>>,
+    // Resolves multisampled render targets, requires GLSL 1.50.
+    resolve: Option<PipelineState<R, pipe_resolve::Meta>>,
+    // Renders texture arrays, requires GLSL 1.50.
+    array: Optional<ArrayPipeline<R>>,
+    // The GLSL version of the shaders.
+    glsl: GLSL,
+    // Whether to add anti-aliased fringes to colored shapes.
+    edge_aa: bool,
+    // Collects triangle lists for edge anti-aliasing.
+    fringe: Fringe,
+    // Samplers created from texture settings.
+    samplers: HashMap<gfx::texture::SamplerInfo, gfx::handle::Sampler<R>>,
+    // Whether to draw opaque textures without blending.
+    opaque_no_blend: bool,
+    // Statistics of the current and last frame.
+    stats: StatsRecorder,
+    // Whether GPU times are requested.
+    gpu_timing: bool,
+    // The debug overlay drawn on top of frames.
+    debug: Option<DebugState<R>>,
+    // The color of lines when drawing triangles as wireframe.
+    wireframe_color: Option<[f32; 4]>,
+    // Records `Graphics` calls when set.
+    trace: Option<TraceRecorder<R>>,
 }
 
 impl<R: gfx::Resources> Gfx2d<R> {
     /// Creates a new Gfx2d object.
+    ///
+    /// Pipelines that only some draw calls need are built by the `enable_*` methods,
+    /// e.g. `enable_sdf` for signed distance field text.
     pub fn new<F>(opengl: OpenGL, factory: &mut F) -> Self
         where F: gfx::Factory<R>
     {
@@ -239,8 +722,11 @@ impl<R: gfx::Resources> Gfx2d<R> {
         use gfx::state::{Blend, Stencil};
         use gfx::traits::*;
         use shaders::{ colored, textured, textured_color };
+        use glsl::resolve;
 
+        let start = Instant::now();
         let glsl = opengl.to_glsl();
+        info!("Gfx2d uses GLSL {:?} for OpenGL {:?}", glsl, opengl);
 
         let colored_program = factory.link_program(
                 Shaders::new()
@@ -257,7 +743,7 @@ impl<R: gfx::Resources> Gfx2d<R> {
                                 blend_preset: Blend,
                                 stencil: Stencil,
                                 color_mask: gfx::state::ColorMask|
-        -> PipelineState<R, pipe_colored::Meta> {
+        -> Result<PipelineState<R, pipe_colored::Meta>, PipelineError> {
             factory.create_pipeline_from_program(
                 &colored_program,
                 Primitive::TriangleList,
@@ -270,10 +756,10 @@ impl<R: gfx::Resources> Gfx2d<R> {
                     blend_ref: (),
                     scissor: (),
                 }
-            ).unwrap()
+            ).map_err(PipelineError::from)
         };
 
-        let colored = PsoStencil::new(factory, colored_pipeline);
+        let colored = PsoStencil::new(factory, "colored", colored_pipeline).unwrap();
 
         let textured_program = factory.link_program(
                 Shaders::new()
@@ -290,7 +776,7 @@ impl<R: gfx::Resources> Gfx2d<R> {
                                  blend_preset: Blend,
                                  stencil: Stencil,
                                  color_mask: gfx::state::ColorMask|
-        -> PipelineState<R, pipe_textured::Meta> {
+        -> Result<PipelineState<R, pipe_textured::Meta>, PipelineError> {
             factory.create_pipeline_from_program(
                 &textured_program,
                 Primitive::TriangleList,
@@ -305,10 +791,10 @@ impl<R: gfx::Resources> Gfx2d<R> {
                     blend_ref: (),
                     scissor: (),
                 }
-            ).unwrap()
+            ).map_err(PipelineError::from)
         };
 
-        let textured = PsoStencil::new(factory, textured_pipeline);
+        let textured = PsoStencil::new(factory, "textured", textured_pipeline).unwrap();
 
         let textured_color_program = factory.link_program(
                 Shaders::new()
@@ -325,7 +811,7 @@ impl<R: gfx::Resources> Gfx2d<R> {
                                  blend_preset: Blend,
                                  stencil: Stencil,
                                  color_mask: gfx::state::ColorMask|
-        -> PipelineState<R, pipe_textured_color::Meta> {
+        -> Result<PipelineState<R, pipe_textured_color::Meta>, PipelineError> {
             factory.create_pipeline_from_program(
                 &textured_color_program,
                 Primitive::TriangleList,
@@ -340,10 +826,36 @@ impl<R: gfx::Resources> Gfx2d<R> {
                     blend_ref: (),
                     scissor: (),
                 }
-            ).unwrap()
+            ).map_err(PipelineError::from)
         };
 
-        let textured_color = PsoStencil::new(factory, textured_color_pipeline);
+        let textured_color = PsoStencil::new(factory, "textured_color", textured_color_pipeline)
+            .unwrap();
+
+        let resolve = match (
+            Shaders::new()
+                .set(GLSL::V1_50, resolve::VERTEX_GLSL_150_CORE)
+                .get(glsl),
+            Shaders::new()
+                .set(GLSL::V1_50, resolve::FRAGMENT_GLSL_150_CORE)
+                .get(glsl)
+        ) {
+            (Some(vertex), Some(fragment)) => {
+                let resolve_program = factory.link_program(vertex, fragment).unwrap();
+                Some(factory.create_pipeline_from_program(
+                    &resolve_program,
+                    Primitive::TriangleList,
+                    Rasterizer::new_fill(),
+                    pipe_resolve::Init {
+                        pos: (),
+                        texture: "t_Multisample",
+                        samples: "u_Samples",
+                        target: "o_Color",
+                    }
+                ).unwrap())
+            }
+            _ => None,
+        };
 
         let buffer_pos = factory.create_buffer(
             BUFFER_SIZE * CHUNKS,
@@ -358,22 +870,331 @@ impl<R: gfx::Resources> Gfx2d<R> {
             gfx::memory::Bind::empty()
         ).expect("Could not create `buffer_color`");
         let buffer_uv = factory.create_buffer(
-            BUFFER_SIZE,
+            BUFFER_SIZE * CHUNKS,
             gfx::buffer::Role::Vertex,
             gfx::memory::Usage::Dynamic,
             gfx::memory::Bind::empty()
         ).expect("Could not create `buffer_uv`");
 
+        let mut pipelines = 3 * PSO_STENCIL_LEN;
+        let vertex_size = size_of::<PositionFormat>() + size_of::<ColorFormat>() +
+            size_of::<TexCoordsFormat>();
+        if resolve.is_some() {
+            pipelines += 1;
+        } else {
+            debug!("Resolving and texture arrays are disabled, they require GLSL 1.50");
+        }
+        debug!("Vertex buffers hold {} vertices in {} bytes",
+            BUFFER_SIZE * CHUNKS, BUFFER_SIZE * CHUNKS * vertex_size);
+        info!("Gfx2d built {} pipeline states in {:?}", pipelines, start.elapsed());
+
         Gfx2d {
             colored_offset: 0,
             colored_draw_state: Default::default(),
-            buffer_pos: buffer_pos,
-            buffer_color: buffer_color,
-            buffer_uv: buffer_uv,
-            colored: colored,
-            textured: textured,
-            textured_color: textured_color,
+            textured_offset: 0,
+            textured_batch: None,
+            buffer_pos,
+            buffer_color,
+            buffer_uv,
+            colored,
+            textured,
+            textured_color,
+            sdf: Optional::Disabled,
+            channels: Optional::Disabled,
+            channels_color: Optional::Disabled,
+            wireframe: Optional::Disabled,
+            resolve,
+            array: Optional::Disabled,
+            glsl,
+            edge_aa: false,
+            fringe: Fringe::default(),
+            samplers: HashMap::new(),
+            opaque_no_blend: false,
+            stats: StatsRecorder::new(),
+            gpu_timing: false,
+            debug: None,
+            wireframe_color: None,
+            trace: None,
+        }
+    }
+
+    /// Returns statistics of the last frame rendered with `draw`.
+    pub fn frame_stats(&self) -> &FrameStats {
+        self.stats.last()
+    }
+
+    /// Enables or disables measuring GPU time of frames.
+    pub fn set_gpu_timing(&mut self, enabled: bool) {
+        self.gpu_timing = enabled;
+    }
+
+    /// Returns true if measuring GPU time of frames is enabled.
+    pub fn gpu_timing(&self) -> bool {
+        self.gpu_timing
+    }
+
+    /// Returns GPU times of an earlier frame.
+    ///
+    /// Reports `GpuTimesError::Unavailable` when the back-end can not record
+    /// timer queries, so profiling code can fall back instead of failing.
+    /// The command buffers of gfx 0.18 have no query commands,
+    /// which makes timing unavailable on all current back-ends.
+    pub fn gpu_times(&self) -> Result<GpuTimes, GpuTimesError> {
+        if self.gpu_timing {
+            Err(GpuTimesError::Unavailable)
+        } else {
+            Err(GpuTimesError::Disabled)
+        }
+    }
+
+    /// Sets the debug overlay drawn on top of frames, or disables it with `None`.
+    ///
+    /// Returns the previous overlay, e.g. to reuse its render target.
+    pub fn set_debug_overlay(
+        &mut self,
+        overlay: Option<DebugOverlay<R>>
+    ) -> Option<DebugOverlay<R>> {
+        let previous = self.debug.take().map(|debug| debug.overlay);
+        self.debug = overlay.map(DebugState::new);
+        previous
+    }
+
+    /// Returns the debug overlay drawn on top of frames.
+    pub fn debug_overlay(&self) -> Option<&DebugOverlay<R>> {
+        self.debug.as_ref().map(|debug| &debug.overlay)
+    }
+
+    /// Draws the edges of triangles as lines in a color instead of filling them,
+    /// or fills them again with `None`.
+    ///
+    /// Shows the tessellation of shapes, images and text.
+    /// Clipping shapes are still written to the stencil buffer.
+    ///
+    /// Requires `enable_wireframe`, otherwise the error is logged
+    /// and triangles stay filled.
+    pub fn set_wireframe(&mut self, color: Option<[f32; 4]>) {
+        if color.is_some() && self.wireframe.get_mut().is_none() {
+            error!("Call `Gfx2d::enable_wireframe` before drawing wireframe");
+            return;
+        }
+        self.wireframe_color = color;
+    }
+
+    /// Builds the line pipelines and edge indices for drawing wireframe.
+    ///
+    /// Call this before `set_wireframe`.
+    pub fn enable_wireframe<F>(&mut self, factory: &mut F) -> Result<(), Box<dyn Error>>
+        where F: gfx::Factory<R>
+    {
+        if self.wireframe.get_mut().is_none() {
+            self.wireframe = Optional::Enabled(wireframe_pipelines(factory, self.glsl)?);
+        }
+        Ok(())
+    }
+
+    /// Builds the pipelines for signed distance fields.
+    ///
+    /// Call this before drawing with `GfxGraphics::tri_list_uv_sdf` or `SdfText`,
+    /// which are skipped otherwise.
+    pub fn enable_sdf<F>(&mut self, factory: &mut F) -> Result<(), Box<dyn Error>>
+        where F: gfx::Factory<R>
+    {
+        if self.sdf.get_mut().is_none() {
+            self.sdf = Optional::Enabled(sdf_pipelines(factory, self.glsl)?);
+        }
+        Ok(())
+    }
+
+    /// Builds the pipelines for single and two channel textures.
+    ///
+    /// Call this before drawing textures created with `ChannelTexture`,
+    /// which are skipped otherwise.
+    pub fn enable_channels<F>(&mut self, factory: &mut F) -> Result<(), Box<dyn Error>>
+        where F: gfx::Factory<R>
+    {
+        if self.channels.get_mut().is_none() {
+            self.channels = Optional::Enabled(channels_pipelines(factory, self.glsl)?);
+        }
+        if self.channels_color.get_mut().is_none() {
+            self.channels_color =
+                Optional::Enabled(channels_color_pipelines(factory, self.glsl)?);
+        }
+        Ok(())
+    }
+
+    /// Builds the pipelines and layer buffer for texture arrays.
+    ///
+    /// Call this before drawing a `TextureArray`, which is skipped otherwise.
+    /// Fails if texture arrays are not supported,
+    /// see `supports_texture_arrays`.
+    pub fn enable_texture_arrays<F>(&mut self, factory: &mut F) -> Result<(), Box<dyn Error>>
+        where F: gfx::Factory<R>
+    {
+        if self.array.get_mut().is_none() {
+            self.array = Optional::Enabled(array_pipelines(factory, self.glsl)?);
+        }
+        Ok(())
+    }
+
+    /// Returns the color of lines when triangles are drawn as wireframe.
+    pub fn wireframe(&self) -> Option<[f32; 4]> {
+        self.wireframe_color
+    }
+
+    /// Starts recording `Graphics` calls of `GfxGraphics` into a new trace.
+    ///
+    /// Recording continues across frames until `stop_recording`.
+    /// Textures used by recorded calls are kept alive while recording.
+    pub fn start_recording(&mut self) {
+        self.trace = Some(TraceRecorder::new());
+    }
+
+    /// Stops recording and returns the trace, or `None` if not recording.
+    pub fn stop_recording(&mut self) -> Option<Trace> {
+        self.trace.take().map(|recorder| recorder.trace)
+    }
+
+    /// Returns true if `Graphics` calls are recorded.
+    pub fn is_recording(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    // Returns `false` when the pipelines of a textured program are not enabled.
+    fn require_textured(&mut self, program: TexturedProgram) -> bool {
+        let Gfx2d { ref mut sdf, ref mut channels, ref mut array, .. } = *self;
+        match program {
+            TexturedProgram::Standard => true,
+            TexturedProgram::Sdf => sdf.require("enable_sdf").is_some(),
+            TexturedProgram::Array => array.require("enable_texture_arrays").is_some(),
+            TexturedProgram::Alpha | TexturedProgram::LuminanceAlpha => {
+                channels.require("enable_channels").is_some()
+            }
+        }
+    }
+
+    // Returns true if triangles with a draw state are drawn as wireframe.
+    fn draws_wireframe(&self, draw_state: &DrawState) -> bool {
+        use graphics::draw_state::Stencil;
+
+        match draw_state.stencil {
+            Some(Stencil::Clip(_)) => false,
+            _ => self.wireframe_color.is_some(),
+        }
+    }
+
+    /// Returns a sampler matching texture settings.
+    ///
+    /// Samplers are created on first use and cached by their settings,
+    /// so this is cheap to call every frame.
+    /// Draw with the sampler using `GfxGraphics::with_sampler`.
+    pub fn sampler<F>(
+        &mut self,
+        factory: &mut F,
+        settings: &TextureSettings
+    ) -> gfx::handle::Sampler<R>
+        where F: gfx::Factory<R>
+    {
+        let info = sampler_info(settings);
+        self.samplers.entry(info)
+            .or_insert_with(|| factory.create_sampler(info))
+            .clone()
+    }
+
+    /// Enables or disables edge anti-aliasing of colored shapes.
+    ///
+    /// Adds a one pixel wide fringe fading to transparent along the outer
+    /// edges of shapes drawn with `tri_list` and `tri_list_c`.
+    /// This gives smooth edges on single sampled targets without MSAA.
+    ///
+    /// Only applies to shapes drawn with alpha blending,
+    /// and not when drawing to the stencil buffer.
+    /// Disabled by default.
+    pub fn set_edge_aa(&mut self, enabled: bool) {
+        self.edge_aa = enabled;
+    }
+
+    /// Returns true if edge anti-aliasing of colored shapes is enabled.
+    pub fn edge_aa(&self) -> bool {
+        self.edge_aa
+    }
+
+    /// Enables or disables drawing opaque textures without blending.
+    ///
+    /// When enabled, `tri_list_uv` with alpha blending and a fully opaque color
+    /// uses the pipeline without blending for textures where
+    /// `GfxGraphics::has_texture_alpha` is false.
+    /// Textures with an alpha channel can be drawn the same way
+    /// inside `GfxGraphics::with_opaque_textures`.
+    /// This reduces fill-rate cost of large backgrounds.
+    /// Disabled by default.
+    pub fn set_opaque_no_blend(&mut self, enabled: bool) {
+        self.opaque_no_blend = enabled;
+    }
+
+    /// Returns true if opaque textures are drawn without blending.
+    pub fn opaque_no_blend(&self) -> bool {
+        self.opaque_no_blend
+    }
+
+    /// Returns true if multisampled render targets can be resolved.
+    ///
+    /// Resolving requires GLSL 1.50 or later.
+    pub fn supports_resolve(&self) -> bool {
+        self.resolve.is_some()
+    }
+
+    /// Returns true if texture arrays can be rendered.
+    ///
+    /// Texture arrays require GLSL 1.50 or later.
+    pub fn supports_texture_arrays(&self) -> bool {
+        self.glsl >= GLSL::V1_50
+    }
+
+    /// Resolves a multisampled render target into its texture.
+    ///
+    /// Call this after drawing to the target and before sampling
+    /// from `RenderTarget::texture`.
+    /// Does nothing for single sampled targets.
+    ///
+    /// Panics if the target is multisampled and resolving is not supported,
+    /// see `Gfx2d::supports_resolve`.
+    pub fn resolve<C>(
+        &mut self,
+        encoder: &mut gfx::Encoder<R, C>,
+        target: &RenderTarget<R>
+    )
+        where C: gfx::CommandBuffer<R>
+    {
+        let multisample = match target.multisample() {
+            None => return,
+            Some(multisample) => multisample,
+        };
+        let pso = self.resolve.as_ref()
+            .expect("Resolving multisampled targets requires GLSL 1.50");
+
+        if !write_buffer(encoder, &self.buffer_pos, unsafe {
+            ::std::slice::from_raw_parts(
+                FULLSCREEN_TRIANGLE.as_ptr() as *const PositionFormat,
+                FULLSCREEN_TRIANGLE.len()
+            )
+        }, 0) {
+            return;
         }
+
+        let data = pipe_resolve::Data {
+            pos: self.buffer_pos.clone(),
+            texture: multisample.view.clone(),
+            samples: multisample.samples as i32,
+            target: multisample.resolve.clone(),
+        };
+        let slice = gfx::Slice {
+            instances: None,
+            start: 0,
+            end: FULLSCREEN_TRIANGLE.len() as u32,
+            buffer: gfx::IndexBuffer::Auto,
+            base_vertex: 0,
+        };
+        encoder.draw(&slice, pso, &data);
     }
 
     /// Renders graphics to a Gfx renderer.
@@ -388,21 +1209,45 @@ impl<R: gfx::Resources> Gfx2d<R> {
         where C: gfx::CommandBuffer<R>,
               F: FnOnce(Context, &mut GfxGraphics<R, C>) -> U
     {
-        let ref mut g = GfxGraphics::new(
-            encoder,
-            output_color,
-            output_stencil,
-            self
-        );
-        let c = Context::new_viewport(viewport);
-        let res = f(c, g);
-        if g.g2d.colored_offset > 0 {
-            g.flush_colored();
+        self.stats.begin_frame();
+        if let Some(ref mut debug) = self.debug {
+            debug.begin_frame();
+            if let DebugOverlay::Overdraw(ref target) = debug.overlay {
+                encoder.clear(&target.output_color, [0.0, 0.0, 0.0, 1.0]);
+            }
         }
+        let res = {
+            let ref mut g = GfxGraphics::new(
+                encoder,
+                output_color,
+                output_stencil,
+                self
+            );
+            let c = Context::new_viewport(viewport);
+            let res = f(c, g);
+            g.flush();
+            g.draw_overdraw();
+            res
+        };
+        self.stats.end_frame();
         res
     }
 }
 
+// Selects the program for a texture by its surface format.
+//
+// Single and two channel textures are swizzled to draw as alpha masks
+// or luminance with alpha instead of red and green.
+fn texture_program<R: gfx::Resources>(texture: &Texture<R>) -> TexturedProgram {
+    use gfx::format::SurfaceType;
+
+    match texture.surface.get_info().format {
+        SurfaceType::R8 => TexturedProgram::Alpha,
+        SurfaceType::R8_G8 => TexturedProgram::LuminanceAlpha,
+        _ => TexturedProgram::Standard,
+    }
+}
+
 /// Used for rendering 2D graphics.
 pub struct GfxGraphics<'a, R, C>
     where R: gfx::Resources + 'a,
@@ -419,6 +1264,12 @@ pub struct GfxGraphics<'a, R, C>
     output_color: &'a gfx::handle::RenderTargetView<R, Srgba8>,
     output_stencil: &'a gfx::handle::DepthStencilView<R, DepthStencil>,
     g2d: &'a mut Gfx2d<R>,
+    // Stack of intersected scissor rectangles.
+    scissor_stack: Vec<[u32; 4]>,
+    // Replaces the sampler of textures when set.
+    sampler: Option<gfx::handle::Sampler<R>>,
+    // Whether textures are drawn as opaque, see `with_opaque_textures`.
+    opaque_textures: bool,
 }
 
 impl<'a, R, C> GfxGraphics<'a, R, C>
@@ -431,49 +1282,207 @@ impl<'a, R, C> GfxGraphics<'a, R, C>
                output_stencil: &'a gfx::handle::DepthStencilView<R, DepthStencil>,
                g2d: &'a mut Gfx2d<R>) -> Self {
         GfxGraphics {
-            encoder: encoder,
-            output_color: output_color,
-            output_stencil: output_stencil,
-            g2d: g2d,
+            encoder,
+            output_color,
+            output_stencil,
+            g2d,
+            scissor_stack: vec![],
+            sampler: None,
+            opaque_textures: false,
         }
     }
 
-    /// Returns true if texture has alpha channel.
-    pub fn has_texture_alpha(&self, texture: &Texture<R>) -> bool
-        where R: gfx::Resources
+    /// Returns an object rendering with `TextureRegion` as texture type.
+    pub fn regions<'b>(&'b mut self) -> RegionGraphics<'b, 'a, R, C> {
+        RegionGraphics::new(self)
+    }
+
+    /// Pushes a scissor rectangle `[x, y, w, h]` in screen space.
+    ///
+    /// The rectangle is intersected with the current scissor,
+    /// such that nested widgets never draw outside their parents.
+    /// The result is also intersected with `DrawState::scissor` when drawing.
+    pub fn push_scissor(&mut self, rect: [u32; 4]) {
+        let rect = match self.scissor_stack.last() {
+            None => rect,
+            Some(&top) => intersect_scissor(top, rect),
+        };
+        self.scissor_stack.push(rect);
+    }
+
+    /// Pops the last pushed scissor rectangle.
+    ///
+    /// Returns `None` if the scissor stack is empty.
+    pub fn pop_scissor(&mut self) -> Option<[u32; 4]> {
+        self.scissor_stack.pop()
+    }
+
+    /// Returns the current scissor rectangle of the scissor stack.
+    pub fn current_scissor(&self) -> Option<[u32; 4]> {
+        self.scissor_stack.last().cloned()
+    }
+
+    /// Draws textures with another sampler inside the closure.
+    ///
+    /// This changes wrap modes, filtering and border color
+    /// of existing textures without uploading them again.
+    /// Applies to all textured triangles drawn in the closure.
+    /// Get a cached sampler from texture settings with `Gfx2d::sampler`.
+    pub fn with_sampler<F, U>(&mut self, sampler: &gfx::handle::Sampler<R>, f: F) -> U
+        where F: FnOnce(&mut Self) -> U
+    {
+        let prev = self.sampler.replace(sampler.clone());
+        let res = f(self);
+        self.sampler = prev;
+        res
+    }
+
+    /// Draws textures as opaque inside the closure.
+    ///
+    /// Use this for textures known to have no transparent pixels,
+    /// such as backgrounds loaded from images with an alpha channel.
+    /// With `Gfx2d::set_opaque_no_blend` enabled,
+    /// textured triangles drawn at full opacity in the closure skip blending.
+    pub fn with_opaque_textures<F, U>(&mut self, f: F) -> U
+        where F: FnOnce(&mut Self) -> U
     {
-        texture.surface.get_info().format.get_alpha_stencil_bits() > 0
+        let prev = mem::replace(&mut self.opaque_textures, true);
+        let res = f(self);
+        self.opaque_textures = prev;
+        res
     }
 
-    fn flush_colored(&mut self) {
-        use draw_state::target::Rect;
-        use std::u16;
+    // Returns draw state with scissor intersected by the scissor stack.
+    fn clip_draw_state(&self, draw_state: &DrawState) -> DrawState {
+        let mut draw_state = *draw_state;
+        if let Some(top) = self.current_scissor() {
+            draw_state.scissor = Some(match draw_state.scissor {
+                None => top,
+                Some(r) => intersect_scissor(top, r),
+            });
+        }
+        draw_state
+    }
+
+    /// Returns true if texture has alpha channel.
+    ///
+    /// Returns false for formats without alpha, such as BC1.
+    /// Single and two channel textures store alpha in a color channel.
+    pub fn has_texture_alpha(&self, texture: &Texture<R>) -> bool {
+        match texture_program(texture) {
+            TexturedProgram::Standard => {
+                texture.surface.get_info().format.get_alpha_stencil_bits() > 0
+            }
+            _ => true,
+        }
+    }
+
+    // Adds vertices with linear colors to the colored batch.
+    fn push_colored(&mut self, vertices: &[[f32; 2]], colors: &[[f32; 4]]) {
+        if self.g2d.textured_offset > 0 {
+            self.flush_textured();
+        }
+        self.upload_colored(vertices, colors);
+    }
+
+    // Adds the fringe around the outer edges of a triangle list
+    // to the colored batch, for edge anti-aliasing.
+    fn push_fringe(&mut self, fringe: &mut Fringe) {
+        let (w, h, _, _) = self.output_color.get_dimensions();
+        let (vertices, colors) = fringe.fringe([w as f32 / 2.0, h as f32 / 2.0]);
+        let chunk = BUFFER_SIZE / 3 * 3;
+        for (vertices, colors) in vertices.chunks(chunk).zip(colors.chunks(chunk)) {
+            self.push_colored(vertices, colors);
+        }
+    }
+
+    // Uploads vertices with linear colors, rendering if there is not enough room.
+    fn upload_colored(&mut self, vertices: &[[f32; 2]], colors: &[[f32; 4]]) {
+        use std::slice::from_raw_parts;
+
+        let n = vertices.len();
+        assert_eq!(n, colors.len());
+
+        // Render if there is not enough room.
+        if self.g2d.colored_offset + n > BUFFER_SIZE * CHUNKS {
+            self.flush_colored();
+        }
+        if let Some(ref mut debug) = self.g2d.debug {
+            debug.add_vertices(vertices);
+        }
 
         let &mut GfxGraphics {
             ref mut encoder,
-            output_color,
-            output_stencil,
             g2d: &mut Gfx2d {
                 ref mut colored_offset,
-                ref mut colored_draw_state,
                 ref mut buffer_pos,
                 ref mut buffer_color,
-                ref mut colored,
+                ref mut stats,
                 ..
             },
             ..
         } = self;
 
-        let (pso_colored, stencil_val) = colored.stencil_blend(
-            colored_draw_state.stencil,
-            colored_draw_state.blend
-        );
+        unsafe {
+            if !write_buffer(
+                encoder,
+                buffer_pos,
+                from_raw_parts(
+                    vertices.as_ptr() as *const PositionFormat,
+                    n
+                ),
+                *colored_offset
+            ) {
+                return;
+            }
+            if !write_buffer(
+                encoder,
+                buffer_color,
+                from_raw_parts(
+                    colors.as_ptr() as *const ColorFormat,
+                    n
+                ),
+                *colored_offset
+            ) {
+                return;
+            }
+        }
+        stats.upload(n, n * size_of::<PositionFormat>(), n * size_of::<ColorFormat>(), 0);
+        *colored_offset += n;
+    }
 
-        let scissor = match colored_draw_state.scissor {
-            None => Rect { x: 0, y: 0, w: u16::MAX, h: u16::MAX },
-            Some(r) => Rect { x: r[0] as u16, y: r[1] as u16,
-                w: r[2] as u16, h: r[3] as u16 }
-        };
+    fn flush_colored(&mut self) {
+        if self.g2d.draws_wireframe(&self.g2d.colored_draw_state) {
+            let n = self.g2d.colored_offset;
+            let draw_state = self.g2d.colored_draw_state;
+            self.g2d.colored_offset = 0;
+            self.g2d.stats.colored_flush();
+            self.draw_wireframe(n, &draw_state);
+            self.draw_debug(n, &draw_state);
+            return;
+        }
+        let &mut GfxGraphics {
+            ref mut encoder,
+            output_color,
+            output_stencil,
+            g2d: &mut Gfx2d {
+                ref mut colored_offset,
+                ref mut colored_draw_state,
+                ref mut buffer_pos,
+                ref mut buffer_color,
+                ref mut colored,
+                ref mut stats,
+                ..
+            },
+            ..
+        } = self;
+
+        let (pso_colored, stencil_val) = colored.stencil_blend(
+            colored_draw_state.stencil,
+            colored_draw_state.blend
+        );
+
+        let scissor = scissor_rect(colored_draw_state.scissor);
 
         let data = pipe_colored::Data {
             pos: buffer_pos.clone(),
@@ -483,7 +1492,7 @@ impl<'a, R, C> GfxGraphics<'a, R, C>
                              (stencil_val, stencil_val)),
             // Use white color for blend reference to make invert work.
             blend_ref: [1.0; 4],
-            scissor: scissor,
+            scissor,
         };
 
         let slice = gfx::Slice {
@@ -494,337 +1503,1049 @@ impl<'a, R, C> GfxGraphics<'a, R, C>
             base_vertex: 0,
         };
         encoder.draw(&slice, pso_colored, &data);
+        stats.colored_flush();
+        stats.draw(Pipeline::Colored, colored_draw_state);
+        let n = *colored_offset;
+        let draw_state = *colored_draw_state;
         *colored_offset = 0;
+        self.draw_debug(n, &draw_state);
     }
-}
 
-impl<'a, R, C> Graphics for GfxGraphics<'a, R, C>
-    where R: gfx::Resources,
-          C: gfx::CommandBuffer<R>,
-          R::Buffer: 'a,
-          R::Shader: 'a,
-          R::Program: 'a,
-          R::Texture: 'a,
-          R::Sampler: 'a
-{
-    type Texture = Texture<R>;
-
-    fn clear_color(&mut self, color: [f32; 4]) {
-        let color = gamma_srgb_to_linear(color);
-        let &mut GfxGraphics {
-            ref mut encoder,
-            output_color,
-            ..
-        } = self;
-        encoder.clear(output_color, color);
-    }
-
-    fn clear_stencil(&mut self, value: u8) {
-        let &mut GfxGraphics {
-            ref mut encoder,
-            output_stencil,
-            ..
-        } = self;
-        encoder.clear_stencil(output_stencil, value);
+    /// Renders list of 2d triangles using a signed distance field texture.
+    ///
+    /// The distance to the edge is read from the alpha channel of the texture,
+    /// where values above 0.5 are inside the shape.
+    /// The shape is filled with a color and anti-aliased at any scale.
+    ///
+    /// Works like `Graphics::tri_list_uv` otherwise.
+    /// Used for rendering text with `SdfText`.
+    /// Requires `Gfx2d::enable_sdf`.
+    pub fn tri_list_uv_sdf<F>(
+        &mut self,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        texture: &Texture<R>,
+        f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]]))
+    {
+        self.draw_textured(TexturedProgram::Sdf, draw_state, color, texture, f)
     }
 
-    fn tri_list<F>(
+    /// Renders list of 2d triangles using layers of a texture array.
+    ///
+    /// The closure receives a layer index per vertex in addition to
+    /// vertices and texture coordinates.
+    /// Triangles using different layers of the same array are batched
+    /// into a single draw call.
+    ///
+    /// Works like `Graphics::tri_list_uv` otherwise.
+    /// Requires `Gfx2d::enable_texture_arrays`.
+    /// Panics if texture arrays are not supported,
+    /// see `Gfx2d::supports_texture_arrays`.
+    pub fn tri_list_uv_layer<F>(
         &mut self,
         draw_state: &DrawState,
         color: &[f32; 4],
+        texture: &TextureArray<R>,
         mut f: F
     )
-        where F: FnMut(&mut dyn FnMut(&[[f32; 2]]))
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[f32]))
     {
+        assert!(self.g2d.supports_texture_arrays(),
+                "Texture arrays require GLSL 1.50 or later");
+        if !self.begin_textured(TexturedProgram::Array, draw_state, color,
+                                texture.view(), texture.sampler()) {
+            return;
+        }
+        f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]], layers: &[f32]| {
+            self.upload_textured(vertices, texture_coords, Some(layers))
+        })
+    }
+
+    /// Draws a layer of a texture array in a rectangle.
+    ///
+    /// Consecutive layers of the same array are batched,
+    /// which makes this suitable for tilemaps and animated sprites.
+    ///
+    /// Panics if the layer is out of bounds.
+    pub fn image_layer(
+        &mut self,
+        texture: &TextureArray<R>,
+        layer: u32,
+        color: &[f32; 4],
+        rect: [f64; 4],
+        draw_state: &DrawState,
+        transform: Matrix2d
+    ) {
+        use graphics::triangulation::rect_tri_list_xy;
+
+        assert!(layer < texture.layers(),
+                "Layer {} is out of bounds of texture array with {} layers",
+                layer, texture.layers());
+        let vertices = rect_tri_list_xy(transform, rect);
+        let uvs = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        self.tri_list_uv_layer(draw_state, color, texture, |f| {
+            f(&vertices, &uvs, &[layer as f32; 6])
+        });
+    }
+
+    // Renders colored triangles, see `Graphics::tri_list`.
+    //
+    // Takes trait objects to share one instantiation with replayed traces.
+    fn colored_tri_list(
+        &mut self,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        f: &mut TriListFn
+    ) {
         let color = gamma_srgb_to_linear(*color);
+        let draw_state = &self.clip_draw_state(draw_state);
 
         // Flush when draw state changes.
         if &self.g2d.colored_draw_state != draw_state {
             self.flush_colored();
             self.g2d.colored_draw_state = *draw_state;
         }
+        // The fringe is added once outer edges of the whole list are known.
+        let edge_aa = self.g2d.edge_aa && supports_edge_aa(draw_state);
+        let mut fringe = mem::take(&mut self.g2d.fringe);
+        fringe.clear();
+        let mut colors = vec![];
         f(&mut |vertices: &[[f32; 2]]| {
-            let n = vertices.len();
-
-            // Render if there is not enough room.
-            if self.g2d.colored_offset + n > BUFFER_SIZE * CHUNKS {
-                self.flush_colored();
+            colors.clear();
+            colors.resize(vertices.len(), color);
+            if edge_aa {
+                fringe.extend(vertices, &colors);
             }
-
-            {
-                use std::slice::from_raw_parts;
-
-                let &mut GfxGraphics {
-                    ref mut encoder,
-                    g2d: &mut Gfx2d {
-                        ref mut colored_offset,
-                        ref mut buffer_pos,
-                        ref mut buffer_color,
-                        ..
-                    },
-                    ..
-                } = self;
-
-                unsafe {
-                    encoder.update_buffer(
-                        &buffer_pos,
-                        from_raw_parts(
-                            vertices.as_ptr() as *const PositionFormat,
-                            n
-                        ),
-                        *colored_offset
-                    ).unwrap();
-                }
-
-                for i in 0..n {
-                    encoder.update_buffer(&buffer_color, &[ColorFormat {
-                            color: color
-                        }], *colored_offset + i).unwrap();
-                }
-                *colored_offset += n;
-            }
-        })
+            self.push_colored(vertices, &colors);
+        });
+        if edge_aa {
+            self.push_fringe(&mut fringe);
+        }
+        self.g2d.fringe = fringe;
     }
 
-    fn tri_list_c<F>(
+    // Renders triangles with colors per vertex, see `Graphics::tri_list_c`.
+    fn colored_tri_list_c(
         &mut self,
         draw_state: &DrawState,
-        mut f: F
-    )
-        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]]))
-    {
+        f: &mut TriListCFn
+    ) {
+        let draw_state = &self.clip_draw_state(draw_state);
+
         // Flush when draw state changes.
         if &self.g2d.colored_draw_state != draw_state {
             self.flush_colored();
             self.g2d.colored_draw_state = *draw_state;
         }
+        // The fringe is added once outer edges of the whole list are known.
+        let edge_aa = self.g2d.edge_aa && supports_edge_aa(draw_state);
+        let mut fringe = mem::take(&mut self.g2d.fringe);
+        fringe.clear();
+        let mut linear_colors = vec![];
         f(&mut |vertices: &[[f32; 2]], colors: &[[f32; 4]]| {
-            let n = vertices.len();
-
-            // Render if there is not enough room.
-            if self.g2d.colored_offset + n > BUFFER_SIZE * CHUNKS {
-                self.flush_colored();
+            linear_colors.clear();
+            linear_colors.extend(colors.iter().map(|&color| gamma_srgb_to_linear(color)));
+            if edge_aa {
+                fringe.extend(vertices, &linear_colors);
             }
+            self.push_colored(vertices, &linear_colors);
+        });
+        if edge_aa {
+            self.push_fringe(&mut fringe);
+        }
+        self.g2d.fringe = fringe;
+    }
 
-            {
-                use std::slice::from_raw_parts;
+    // Renders textured triangles, see `Graphics::tri_list_uv`.
+    fn textured_tri_list(
+        &mut self,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        texture: &Texture<R>,
+        f: &mut TriListUvFn
+    ) {
+        use graphics::draw_state::Blend;
 
-                let &mut GfxGraphics {
-                    ref mut encoder,
-                    g2d: &mut Gfx2d {
-                        ref mut colored_offset,
-                        ref mut buffer_pos,
-                        ref mut buffer_color,
-                        ..
-                    },
-                    ..
-                } = self;
+        let program = texture_program(texture);
+        // Opaque textures at full opacity look the same without blending.
+        let opaque = self.opaque_textures || !self.has_texture_alpha(texture);
+        if self.g2d.opaque_no_blend && draw_state.blend == Some(Blend::Alpha) &&
+           color[3] >= 1.0 && opaque {
+            let draw_state = DrawState { blend: None, ..*draw_state };
+            return self.draw_textured(program, &draw_state, color, texture, f);
+        }
+        self.draw_textured(program, draw_state, color, texture, f)
+    }
 
-                unsafe {
-                    encoder.update_buffer(
-                        &buffer_pos,
-                        from_raw_parts(
-                            vertices.as_ptr() as *const PositionFormat,
-                            n
-                        ),
-                        *colored_offset
-                    ).unwrap();
-                }
+    // Renders textured triangles with colors per vertex, see `Graphics::tri_list_uv_c`.
+    fn textured_tri_list_c(
+        &mut self,
+        draw_state: &DrawState,
+        texture: &Texture<R>,
+        f: &mut TriListUvCFn
+    ) {
+        // Single and two channel textures are swizzled like in `textured_tri_list`.
+        let luminance = match texture_program(texture) {
+            TexturedProgram::Alpha => Some(0.0),
+            TexturedProgram::LuminanceAlpha => Some(1.0),
+            _ => None,
+        };
+        if luminance.is_some() && self.g2d.channels_color.require("enable_channels").is_none() {
+            return;
+        }
+        let draw_state = &self.clip_draw_state(draw_state);
+        let sampler = self.sampler.as_ref().unwrap_or(&texture.sampler).clone();
+        self.flush();
+
+        let stencil_val = self.g2d.textured_color.stencil_blend(
+            draw_state.stencil,
+            draw_state.blend
+        ).1;
+
+        let scissor = scissor_rect(draw_state.scissor);
+
+        let data = pipe_textured_color::Data {
+            pos: self.g2d.buffer_pos.clone(),
+            uv: self.g2d.buffer_uv.clone(),
+            color: self.g2d.buffer_color.clone(),
+            texture: (texture.view.clone(), sampler),
+            blend_target: self.output_color.clone(),
+            stencil_target: (self.output_stencil.clone(),
+                             (stencil_val, stencil_val)),
+            blend_ref: [1.0; 4],
+            scissor,
+        };
+        let channels_data = luminance.map(|luminance| pipe_textured_color_channels::Data {
+            pos: data.pos.clone(),
+            uv: data.uv.clone(),
+            color: data.color.clone(),
+            luminance,
+            texture: data.texture.clone(),
+            blend_target: data.blend_target.clone(),
+            stencil_target: data.stencil_target.clone(),
+            blend_ref: data.blend_ref,
+            scissor: data.scissor,
+        });
+        let wireframe = self.g2d.draws_wireframe(draw_state);
+
+        f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]], colors: &[[f32; 4]]| {
+            use std::slice::from_raw_parts;
 
-                for (i, color) in colors.iter().enumerate() {
-                    encoder.update_buffer(&buffer_color, &[ColorFormat {
-                            color: gamma_srgb_to_linear(*color)
-                        }], *colored_offset + i).unwrap();
+            assert_eq!(
+                vertices.len(),
+                texture_coords.len()
+            );
+            let n = vertices.len();
+            if let Some(ref mut debug) = self.g2d.debug {
+                debug.add_vertices(vertices);
+            }
+            let &mut GfxGraphics {
+                ref mut encoder,
+                g2d: &mut Gfx2d {
+                    ref buffer_pos,
+                    ref buffer_uv,
+                    ref buffer_color,
+                    ref mut textured_color,
+                    ref mut channels_color,
+                    ref mut stats,
+                    ..
+                },
+                ..
+            } = self;
+            unsafe {
+                if !write_buffer(
+                    encoder,
+                    buffer_pos,
+                    from_raw_parts(
+                        vertices.as_ptr() as *const PositionFormat,
+                        n
+                    ),
+                    0
+                ) {
+                    return;
+                }
+                if !write_buffer(
+                    encoder,
+                    buffer_uv,
+                    from_raw_parts(
+                        texture_coords.as_ptr() as *const TexCoordsFormat,
+                        n
+                    ),
+                    0
+                ) {
+                    return;
+                }
+                if !write_buffer(
+                    encoder,
+                    buffer_color,
+                    from_raw_parts(
+                        colors.as_ptr() as *const ColorFormat,
+                        n
+                    ),
+                    0
+                ) {
+                    return;
                 }
-                *colored_offset += n;
             }
+            stats.upload(n, n * size_of::<PositionFormat>(), n * size_of::<ColorFormat>(),
+                         n * size_of::<TexCoordsFormat>());
+
+            if wireframe {
+                self.draw_wireframe(n, draw_state);
+            } else {
+                let slice = gfx::Slice {
+                    instances: None,
+                    start: 0,
+                    end: n as u32,
+                    buffer: gfx::IndexBuffer::Auto,
+                    base_vertex: 0,
+                };
+                if let Some(ref channels_data) = channels_data {
+                    let channels_color = channels_color.get_mut()
+                        .expect("Channel pipelines are built");
+                    let (pso_channels, _) = channels_color.stencil_blend(
+                        draw_state.stencil,
+                        draw_state.blend
+                    );
+                    encoder.draw(&slice, pso_channels, channels_data);
+                    stats.draw(Pipeline::Channels, draw_state);
+                } else {
+                    let (pso_textured_color, _) = textured_color.stencil_blend(
+                        draw_state.stencil,
+                        draw_state.blend
+                    );
+                    encoder.draw(&slice, pso_textured_color, &data);
+                    stats.draw(Pipeline::TexturedColor, draw_state);
+                }
+            }
+            self.draw_debug(n, draw_state);
         })
     }
 
-    fn tri_list_uv<F>(
+    // Renders textured triangles with a uniform color using a program.
+    fn draw_textured<F>(
         &mut self,
+        program: TexturedProgram,
         draw_state: &DrawState,
         color: &[f32; 4],
-        texture: &<Self as Graphics>::Texture,
+        texture: &Texture<R>,
         mut f: F
     )
         where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]]))
     {
-        use draw_state::target::Rect;
-        use std::u16;
+        if !self.begin_textured(program, draw_state, color, &texture.view, &texture.sampler) {
+            return;
+        }
+        f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]]| {
+            self.upload_textured(vertices, texture_coords, None)
+        })
+    }
 
-        let color = gamma_srgb_to_linear(*color);
+    // Starts or continues a batch of textured triangles.
+    //
+    // Consecutive calls with the same program, texture, color and draw state
+    // are batched into a single draw call.
+    // Returns `false` when the pipelines of the program could not be built.
+    fn begin_textured(
+        &mut self,
+        program: TexturedProgram,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        view: &gfx::handle::ShaderResourceView<R, [f32; 4]>,
+        sampler: &gfx::handle::Sampler<R>
+    ) -> bool {
+        if !self.g2d.require_textured(program) {
+            return false;
+        }
+        let batch = TexturedBatch {
+            program,
+            draw_state: self.clip_draw_state(draw_state),
+            color: gamma_srgb_to_linear(*color),
+            view: view.clone(),
+            sampler: self.sampler.as_ref().unwrap_or(sampler).clone(),
+        };
         if self.g2d.colored_offset > 0 {
             self.flush_colored();
         }
+        // Flush when batch changes.
+        if self.g2d.textured_batch.as_ref() != Some(&batch) {
+            self.flush_textured();
+            self.g2d.textured_batch = Some(batch);
+        }
+        true
+    }
+
+    // Adds vertices to the current textured batch.
+    fn upload_textured(
+        &mut self,
+        vertices: &[[f32; 2]],
+        texture_coords: &[[f32; 2]],
+        layers: Option<&[f32]>
+    ) {
+        use std::slice::from_raw_parts;
+
+        assert_eq!(
+            vertices.len(),
+            texture_coords.len()
+        );
+        let n = vertices.len();
+
+        // Render if there is not enough room.
+        if self.g2d.textured_offset + n > BUFFER_SIZE * CHUNKS {
+            self.flush_textured();
+        }
+        if let Some(ref mut debug) = self.g2d.debug {
+            debug.add_vertices(vertices);
+        }
+
+        let &mut GfxGraphics {
+            ref mut encoder,
+            g2d: &mut Gfx2d {
+                ref mut textured_offset,
+                ref mut buffer_pos,
+                ref mut buffer_uv,
+                ref mut array,
+                ref mut stats,
+                ..
+            },
+            ..
+        } = self;
+
+        unsafe {
+            if !write_buffer(
+                encoder,
+                buffer_pos,
+                from_raw_parts(
+                    vertices.as_ptr() as *const PositionFormat,
+                    n
+                ),
+                *textured_offset
+            ) {
+                return;
+            }
+            if !write_buffer(
+                encoder,
+                buffer_uv,
+                from_raw_parts(
+                    texture_coords.as_ptr() as *const TexCoordsFormat,
+                    n
+                ),
+                *textured_offset
+            ) {
+                return;
+            }
+        }
+        if let (Some(layers), Some(array)) = (layers, array.get_mut()) {
+            assert_eq!(vertices.len(), layers.len());
+            unsafe {
+                if !write_buffer(
+                    encoder,
+                    &array.buffer_layer,
+                    from_raw_parts(
+                        layers.as_ptr() as *const LayerFormat,
+                        n
+                    ),
+                    *textured_offset
+                ) {
+                    return;
+                }
+            }
+        }
+        stats.upload(n, n * size_of::<PositionFormat>(), 0, n * size_of::<TexCoordsFormat>());
+        *textured_offset += n;
+    }
+
+    fn flush_textured(&mut self) {
+        let n = self.g2d.textured_offset;
+        let draw_state = self.g2d.textured_batch.as_ref().map(|batch| batch.draw_state);
+        match draw_state {
+            Some(draw_state) if self.g2d.draws_wireframe(&draw_state) => {
+                self.g2d.textured_offset = 0;
+                if n > 0 {
+                    self.draw_wireframe(n, &draw_state);
+                }
+            }
+            _ => self.draw_textured_batch(),
+        }
+        if let (true, Some(draw_state)) = (n > 0, draw_state) {
+            self.draw_debug(n, &draw_state);
+        }
+    }
+
+    // Draws the vertices of the current textured batch.
+    fn draw_textured_batch(&mut self) {
         let &mut GfxGraphics {
             ref mut encoder,
             output_color,
             output_stencil,
             g2d: &mut Gfx2d {
+                ref mut textured_offset,
+                ref mut textured_batch,
                 ref mut buffer_pos,
                 ref mut buffer_uv,
                 ref mut textured,
+                ref mut sdf,
+                ref mut channels,
+                ref mut array,
+                ref mut stats,
                 ..
             },
             ..
         } = self;
 
-        let (pso_textured, stencil_val) = textured.stencil_blend(
-            draw_state.stencil,
-            draw_state.blend
-        );
+        if *textured_offset == 0 {
+            return;
+        }
+        let batch = match textured_batch.as_ref() {
+            None => return,
+            Some(batch) => batch,
+        };
 
-        let scissor = match draw_state.scissor {
-            None => Rect { x: 0, y: 0, w: u16::MAX, h: u16::MAX },
-            Some(r) => Rect { x: r[0] as u16, y: r[1] as u16,
-                w: r[2] as u16, h: r[3] as u16 }
+        let scissor = scissor_rect(batch.draw_state.scissor);
+        let slice = gfx::Slice {
+            instances: None,
+            start: 0,
+            end: *textured_offset as u32,
+            buffer: gfx::IndexBuffer::Auto,
+            base_vertex: 0,
         };
+        *textured_offset = 0;
+
+        let (pso_set, pipeline) = match batch.program {
+            TexturedProgram::Standard => (textured, Pipeline::Textured),
+            TexturedProgram::Sdf => {
+                (sdf.get_mut().expect("SDF pipelines are built"), Pipeline::Sdf)
+            }
+            TexturedProgram::Array => {
+                let array = array.get_mut().expect("Texture array pipelines are built");
+                let (pso_array, stencil_val) = array.pso.stencil_blend(
+                    batch.draw_state.stencil,
+                    batch.draw_state.blend
+                );
+                let data = pipe_textured_array::Data {
+                    pos: buffer_pos.clone(),
+                    uv: buffer_uv.clone(),
+                    layer: array.buffer_layer.clone(),
+                    color: batch.color,
+                    texture: (batch.view.clone(), batch.sampler.clone()),
+                    blend_target: output_color.clone(),
+                    stencil_target: (output_stencil.clone(),
+                                     (stencil_val, stencil_val)),
+                    blend_ref: [1.0; 4],
+                    scissor,
+                };
+                encoder.draw(&slice, pso_array, &data);
+                stats.draw(Pipeline::Array, &batch.draw_state);
+                return;
+            }
+            TexturedProgram::Alpha | TexturedProgram::LuminanceAlpha => {
+                let channels = channels.get_mut().expect("Channel pipelines are built");
+                let (pso_channels, stencil_val) = channels.stencil_blend(
+                    batch.draw_state.stencil,
+                    batch.draw_state.blend
+                );
+                let luminance = if batch.program == TexturedProgram::Alpha { 0.0 } else { 1.0 };
+                let data = pipe_textured_channels::Data {
+                    pos: buffer_pos.clone(),
+                    uv: buffer_uv.clone(),
+                    color: batch.color,
+                    luminance,
+                    texture: (batch.view.clone(), batch.sampler.clone()),
+                    blend_target: output_color.clone(),
+                    stencil_target: (output_stencil.clone(),
+                                     (stencil_val, stencil_val)),
+                    blend_ref: [1.0; 4],
+                    scissor,
+                };
+                encoder.draw(&slice, pso_channels, &data);
+                stats.draw(Pipeline::Channels, &batch.draw_state);
+                return;
+            }
+        };
+        let (pso_textured, stencil_val) = pso_set.stencil_blend(
+            batch.draw_state.stencil,
+            batch.draw_state.blend
+        );
 
         let data = pipe_textured::Data {
             pos: buffer_pos.clone(),
             uv: buffer_uv.clone(),
-            color: color,
-            texture: (texture.view.clone(), texture.sampler.clone()),
+            color: batch.color,
+            texture: (batch.view.clone(), batch.sampler.clone()),
             blend_target: output_color.clone(),
             stencil_target: (output_stencil.clone(),
                              (stencil_val, stencil_val)),
             blend_ref: [1.0; 4],
-            scissor: scissor,
+            scissor,
         };
+        encoder.draw(&slice, pso_textured, &data);
+        stats.draw(pipeline, &batch.draw_state);
+    }
 
-        f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]]| {
-            use std::slice::from_raw_parts;
+    // Draws the edges of `n` vertices written to `buffer_pos` as lines.
+    fn draw_wireframe(&mut self, n: usize, draw_state: &DrawState) {
+        use graphics::draw_state::{Blend, Stencil};
+        use std::slice::from_raw_parts;
 
-            assert_eq!(
-                vertices.len(),
-                texture_coords.len()
-            );
-            let n = vertices.len();
-            unsafe {
-                encoder.update_buffer(
-                    &buffer_pos,
-                    from_raw_parts(
-                        vertices.as_ptr() as *const PositionFormat,
-                        n
-                    ),
-                    0
-                ).unwrap();
-                encoder.update_buffer(
-                    &buffer_uv,
-                    from_raw_parts(
-                        texture_coords.as_ptr() as *const TexCoordsFormat,
-                        n
-                    ),
-                    0
-                ).unwrap();
+        let &mut GfxGraphics {
+            ref mut encoder,
+            output_color,
+            output_stencil,
+            g2d: &mut Gfx2d {
+                ref buffer_pos,
+                ref buffer_color,
+                ref mut wireframe,
+                ref wireframe_color,
+                ref mut stats,
+                ..
+            },
+            ..
+        } = self;
+        let (color, wireframe) = match (*wireframe_color, wireframe.get_mut()) {
+            (Some(color), Some(wireframe)) => (gamma_srgb_to_linear(color), wireframe),
+            _ => return,
+        };
+        let draw_state = DrawState {
+            blend: Some(Blend::Alpha),
+            stencil: match draw_state.stencil {
+                Some(Stencil::Increment) => None,
+                stencil => stencil,
+            },
+            ..*draw_state
+        };
+
+        let colors = vec![color; n];
+        unsafe {
+            if !write_buffer(
+                encoder,
+                buffer_color,
+                from_raw_parts(
+                    colors.as_ptr() as *const ColorFormat,
+                    n
+                ),
+                0
+            ) {
+                return;
             }
+        }
 
-            let slice = gfx::Slice {
-                instances: None,
-                start: 0,
-                end: n as u32,
-                buffer: gfx::IndexBuffer::Auto,
-                base_vertex: 0,
-            };
-            encoder.draw(&slice, pso_textured, &data);
-        })
+        let (pso_wireframe, stencil_val) = wireframe.pso.stencil_blend(
+            draw_state.stencil,
+            draw_state.blend
+        );
+        let data = pipe_colored::Data {
+            pos: buffer_pos.clone(),
+            color: buffer_color.clone(),
+            blend_target: output_color.clone(),
+            stencil_target: (output_stencil.clone(),
+                             (stencil_val, stencil_val)),
+            blend_ref: [1.0; 4],
+            scissor: scissor_rect(draw_state.scissor),
+        };
+        // Two indices per edge of each triangle.
+        let slice = gfx::Slice {
+            instances: None,
+            start: 0,
+            end: (n / 3 * 6) as u32,
+            buffer: gfx::IndexBuffer::Index32(wireframe.index.clone()),
+            base_vertex: 0,
+        };
+        encoder.draw(&slice, pso_wireframe, &data);
+        stats.draw(Pipeline::Wireframe, &draw_state);
     }
 
-    fn tri_list_uv_c<F>(
-        &mut self,
-        draw_state: &DrawState,
-        texture: &<Self as Graphics>::Texture,
-        mut f: F
-    )
-        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]]))
-    {
-        use draw_state::target::Rect;
-        use std::u16;
+    // Draws the debug overlay for a batch of `n` vertices written to `buffer_pos`.
+    fn draw_debug(&mut self, n: usize, draw_state: &DrawState) {
+        use graphics::draw_state::{Blend, Stencil};
+        use std::slice::from_raw_parts;
 
-        if self.g2d.colored_offset > 0 {
-            self.flush_colored();
-        }
         let &mut GfxGraphics {
             ref mut encoder,
             output_color,
             output_stencil,
             g2d: &mut Gfx2d {
-                ref mut buffer_pos,
-                ref mut buffer_uv,
-                ref mut buffer_color,
-                ref mut textured_color,
+                ref buffer_pos,
+                ref buffer_color,
+                ref mut colored,
+                ref mut debug,
                 ..
             },
             ..
         } = self;
+        let debug = match debug.as_mut() {
+            None => return,
+            Some(debug) => debug,
+        };
+        let (color, bounds) = debug.next_batch();
+        let mut draw_state = match draw_state.stencil {
+            // Clipping shapes do not write color.
+            Some(Stencil::Clip(_)) => return,
+            Some(Stencil::Increment) => DrawState { stencil: None, ..*draw_state },
+            _ => *draw_state,
+        };
+        draw_state.blend = Some(Blend::Alpha);
+        let (target, n) = match debug.overlay {
+            DebugOverlay::BatchTint => ((output_color, output_stencil), n),
+            DebugOverlay::BatchBounds => {
+                let bounds = match bounds {
+                    None => return,
+                    Some(bounds) => bounds,
+                };
+                // Outline with a width of one pixel.
+                let (w, h, _, _) = output_color.get_dimensions();
+                let vertices = outline(bounds, [2.0 / w as f32, 2.0 / h as f32]);
+                unsafe {
+                    if !write_buffer(
+                        encoder,
+                        buffer_pos,
+                        from_raw_parts(
+                            vertices.as_ptr() as *const PositionFormat,
+                            vertices.len()
+                        ),
+                        0
+                    ) {
+                        return;
+                    }
+                }
+                draw_state = DrawState::new_alpha();
+                ((output_color, output_stencil), vertices.len())
+            }
+            DebugOverlay::Overdraw(ref target) => {
+                draw_state.stencil = None;
+                draw_state.blend = Some(Blend::Add);
+                ((&target.output_color, &target.output_stencil), n)
+            }
+        };
+
+        let colors = vec![color; n];
+        unsafe {
+            if !write_buffer(
+                encoder,
+                buffer_color,
+                from_raw_parts(
+                    colors.as_ptr() as *const ColorFormat,
+                    n
+                ),
+                0
+            ) {
+                return;
+            }
+        }
 
-        let (pso_textured_color, stencil_val) = textured_color.stencil_blend(
+        let (pso_colored, stencil_val) = colored.stencil_blend(
             draw_state.stencil,
             draw_state.blend
         );
+        let data = pipe_colored::Data {
+            pos: buffer_pos.clone(),
+            color: buffer_color.clone(),
+            blend_target: target.0.clone(),
+            stencil_target: (target.1.clone(),
+                             (stencil_val, stencil_val)),
+            blend_ref: [1.0; 4],
+            scissor: scissor_rect(draw_state.scissor),
+        };
+        let slice = gfx::Slice {
+            instances: None,
+            start: 0,
+            end: n as u32,
+            buffer: gfx::IndexBuffer::Auto,
+            base_vertex: 0,
+        };
+        encoder.draw(&slice, pso_colored, &data);
+    }
 
-        let scissor = match draw_state.scissor {
-            None => Rect { x: 0, y: 0, w: u16::MAX, h: u16::MAX },
-            Some(r) => Rect { x: r[0] as u16, y: r[1] as u16,
-                w: r[2] as u16, h: r[3] as u16 }
+    // Draws the overdraw heatmap over the output at the end of a frame.
+    fn draw_overdraw(&mut self) {
+        use std::slice::from_raw_parts;
+
+        // Maps the fullscreen triangle to the whole texture.
+        const UV: [[f32; 2]; 3] = [[0.0, 0.0], [2.0, 0.0], [0.0, 2.0]];
+
+        let &mut GfxGraphics {
+            ref mut encoder,
+            output_color,
+            output_stencil,
+            g2d: &mut Gfx2d {
+                ref buffer_pos,
+                ref buffer_uv,
+                ref mut textured,
+                ref debug,
+                ..
+            },
+            ..
+        } = self;
+        let texture = match *debug {
+            Some(DebugState { overlay: DebugOverlay::Overdraw(ref target), .. }) =>
+                target.texture(),
+            _ => return,
         };
 
-        let data = pipe_textured_color::Data {
+        unsafe {
+            if !write_buffer(
+                encoder,
+                buffer_pos,
+                from_raw_parts(
+                    FULLSCREEN_TRIANGLE.as_ptr() as *const PositionFormat,
+                    FULLSCREEN_TRIANGLE.len()
+                ),
+                0
+            ) {
+                return;
+            }
+            if !write_buffer(
+                encoder,
+                buffer_uv,
+                from_raw_parts(
+                    UV.as_ptr() as *const TexCoordsFormat,
+                    UV.len()
+                ),
+                0
+            ) {
+                return;
+            }
+        }
+
+        let (pso_textured, stencil_val) = textured.stencil_blend(None, None);
+        let data = pipe_textured::Data {
             pos: buffer_pos.clone(),
             uv: buffer_uv.clone(),
-            color: buffer_color.clone(),
+            color: [1.0; 4],
             texture: (texture.view.clone(), texture.sampler.clone()),
             blend_target: output_color.clone(),
             stencil_target: (output_stencil.clone(),
                              (stencil_val, stencil_val)),
             blend_ref: [1.0; 4],
-            scissor: scissor,
+            scissor: scissor_rect(None),
+        };
+        let slice = gfx::Slice {
+            instances: None,
+            start: 0,
+            end: FULLSCREEN_TRIANGLE.len() as u32,
+            buffer: gfx::IndexBuffer::Auto,
+            base_vertex: 0,
         };
+        encoder.draw(&slice, pso_textured, &data);
+    }
 
-        f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]], colors: &[[f32; 4]]| {
-            use std::slice::from_raw_parts;
+    /// Renders all batched triangles.
+    ///
+    /// Triangles are batched until the draw state, color or texture changes.
+    /// Call this before using `encoder` directly,
+    /// so that commands are executed in order.
+    /// This is done automatically at the end of `Gfx2d::draw`.
+    pub fn flush(&mut self) {
+        if self.g2d.colored_offset > 0 {
+            self.flush_colored();
+        }
+        if self.g2d.textured_offset > 0 {
+            self.flush_textured();
+        }
+    }
+}
 
-            assert_eq!(
-                vertices.len(),
-                texture_coords.len()
-            );
-            let n = vertices.len();
-            unsafe {
-                encoder.update_buffer(
-                    &buffer_pos,
-                    from_raw_parts(
-                        vertices.as_ptr() as *const PositionFormat,
-                        n
-                    ),
-                    0
-                ).unwrap();
-                encoder.update_buffer(
-                    &buffer_uv,
-                    from_raw_parts(
-                        texture_coords.as_ptr() as *const TexCoordsFormat,
-                        n
-                    ),
-                    0
-                ).unwrap();
-                encoder.update_buffer(
-                    &buffer_color,
-                    from_raw_parts(
-                        colors.as_ptr() as *const ColorFormat,
-                        n
-                    ),
-                    0
-                ).unwrap();
-            }
+impl<'a, R, C> Graphics for GfxGraphics<'a, R, C>
+    where R: gfx::Resources,
+          C: gfx::CommandBuffer<R>,
+          R::Buffer: 'a,
+          R::Shader: 'a,
+          R::Program: 'a,
+          R::Texture: 'a,
+          R::Sampler: 'a
+{
+    type Texture = Texture<R>;
 
-            let slice = gfx::Slice {
-                instances: None,
-                start: 0,
-                end: n as u32,
-                buffer: gfx::IndexBuffer::Auto,
-                base_vertex: 0,
-            };
-            encoder.draw(&slice, pso_textured_color, &data);
-        })
+    fn clear_color(&mut self, color: [f32; 4]) {
+        if let Some(ref mut trace) = self.g2d.trace {
+            trace.record(TraceCall::ClearColor(color));
+        }
+        let color = gamma_srgb_to_linear(color);
+        self.flush();
+        let &mut GfxGraphics {
+            ref mut encoder,
+            output_color,
+            ..
+        } = self;
+        encoder.clear(output_color, color);
+    }
+
+    fn clear_stencil(&mut self, value: u8) {
+        if let Some(ref mut trace) = self.g2d.trace {
+            trace.record(TraceCall::ClearStencil(value));
+        }
+        self.flush();
+        let &mut GfxGraphics {
+            ref mut encoder,
+            output_stencil,
+            ..
+        } = self;
+        encoder.clear_stencil(output_stencil, value);
+    }
+
+    fn tri_list<F>(
+        &mut self,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        mut f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]]))
+    {
+        // Collect vertices for the trace, then draw them.
+        if let Some(mut trace) = self.g2d.trace.take() {
+            let mut chunks = vec![];
+            f(&mut |vertices: &[[f32; 2]]| chunks.push(TraceChunk {
+                vertices: vertices.to_vec(),
+                ..TraceChunk::default()
+            }));
+            self.colored_tri_list(draw_state, color, &mut |f| {
+                for chunk in &chunks {
+                    f(&chunk.vertices);
+                }
+            });
+            trace.record(TraceCall::TriList {
+                draw_state: self.clip_draw_state(draw_state),
+                color: *color,
+                chunks,
+            });
+            self.g2d.trace = Some(trace);
+            return;
+        }
+
+        self.colored_tri_list(draw_state, color, &mut f)
+    }
+
+    fn tri_list_c<F>(
+        &mut self,
+        draw_state: &DrawState,
+        mut f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]]))
+    {
+        // Collect vertices for the trace, then draw them.
+        if let Some(mut trace) = self.g2d.trace.take() {
+            let mut chunks = vec![];
+            f(&mut |vertices: &[[f32; 2]], colors: &[[f32; 4]]| chunks.push(TraceChunk {
+                vertices: vertices.to_vec(),
+                colors: colors.to_vec(),
+                ..TraceChunk::default()
+            }));
+            self.colored_tri_list_c(draw_state, &mut |f| {
+                for chunk in &chunks {
+                    f(&chunk.vertices, &chunk.colors);
+                }
+            });
+            trace.record(TraceCall::TriListC {
+                draw_state: self.clip_draw_state(draw_state),
+                chunks,
+            });
+            self.g2d.trace = Some(trace);
+            return;
+        }
+
+        self.colored_tri_list_c(draw_state, &mut f)
+    }
+
+    fn tri_list_uv<F>(
+        &mut self,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        texture: &<Self as Graphics>::Texture,
+        mut f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]]))
+    {
+        // Collect vertices for the trace, then draw them.
+        if let Some(mut trace) = self.g2d.trace.take() {
+            let mut chunks = vec![];
+            f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]]| {
+                chunks.push(TraceChunk {
+                    vertices: vertices.to_vec(),
+                    texture_coords: texture_coords.to_vec(),
+                    ..TraceChunk::default()
+                })
+            });
+            self.textured_tri_list(draw_state, color, texture,
+                                   &mut |f| {
+                for chunk in &chunks {
+                    f(&chunk.vertices, &chunk.texture_coords);
+                }
+            });
+            let texture = trace.texture(texture);
+            trace.record(TraceCall::TriListUv {
+                draw_state: self.clip_draw_state(draw_state),
+                color: *color,
+                texture,
+                chunks,
+            });
+            self.g2d.trace = Some(trace);
+            return;
+        }
+
+        self.textured_tri_list(draw_state, color, texture, &mut f)
+    }
+
+    fn tri_list_uv_c<F>(
+        &mut self,
+        draw_state: &DrawState,
+        texture: &<Self as Graphics>::Texture,
+        mut f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]]))
+    {
+        // Collect vertices for the trace, then draw them.
+        if let Some(mut trace) = self.g2d.trace.take() {
+            let mut chunks = vec![];
+            f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]], colors: &[[f32; 4]]| {
+                chunks.push(TraceChunk {
+                    vertices: vertices.to_vec(),
+                    texture_coords: texture_coords.to_vec(),
+                    colors: colors.to_vec(),
+                })
+            });
+            self.textured_tri_list_c(
+                draw_state,
+                texture,
+                &mut |f| {
+                for chunk in &chunks {
+                    f(&chunk.vertices, &chunk.texture_coords, &chunk.colors);
+                }
+            });
+            let texture = trace.texture(texture);
+            trace.record(TraceCall::TriListUvC {
+                draw_state: self.clip_draw_state(draw_state),
+                texture,
+                chunks,
+            });
+            self.g2d.trace = Some(trace);
+            return;
+        }
+
+        self.textured_tri_list_c(draw_state, texture, &mut f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_scissors_intersect() {
+        assert_eq!(intersect_scissor([0, 0, 100, 100], [50, 20, 100, 30]), [50, 20, 50, 30]);
+        assert_eq!(intersect_scissor([10, 10, 20, 20], [0, 0, 100, 100]), [10, 10, 20, 20]);
+    }
+
+    #[test]
+    fn disjoint_scissors_are_empty() {
+        assert_eq!(intersect_scissor([0, 0, 10, 10], [20, 0, 10, 10]), [20, 0, 0, 0]);
+        // Touching edges do not overlap.
+        assert_eq!(intersect_scissor([0, 0, 10, 10], [0, 10, 10, 10]), [0, 10, 0, 0]);
+    }
+
+    #[test]
+    fn huge_scissors_do_not_overflow() {
+        let max = u32::MAX;
+        assert_eq!(intersect_scissor([10, 10, max, max], [0, 0, 100, 100]), [10, 10, 90, 90]);
     }
 }
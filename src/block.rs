@@ -0,0 +1,364 @@
+//! Decoders for block-compressed pixel data.
+//!
+//! Every format except ASTC encodes 4x4 pixel blocks in 8 or 16 bytes.
+
+use astc;
+use compressed::CompressedFormat;
+
+/// Decodes a level of block-compressed data into RGBA pixels.
+///
+/// Assumes `data` holds all blocks of the level.
+pub(crate) fn decode(format: CompressedFormat, size: [u32; 2], data: &[u8]) -> Vec<u8> {
+    let (w, h) = (size[0] as usize, size[1] as usize);
+    let [bw, bh] = format.block_size();
+    let (bw, bh) = (bw as usize, bh as usize);
+    let blocks_x = w.div_ceil(bw);
+    let block_bytes = format.block_bytes();
+    let mut pixels = vec![0; w * h * 4];
+    let mut block = [[0u8; 4]; 144];
+    for (i, bytes) in data.chunks(block_bytes).enumerate() {
+        let (bx, by) = (i % blocks_x * bw, i / blocks_x * bh);
+        if by >= h {
+            break;
+        }
+        decode_block(format, bytes, &mut block[..bw * bh]);
+        // Pixels are stored row by row in the block.
+        for y in 0..bh.min(h - by) {
+            for x in 0..bw.min(w - bx) {
+                let start = ((by + y) * w + bx + x) * 4;
+                pixels[start..start + 4].copy_from_slice(&block[y * bw + x]);
+            }
+        }
+    }
+    pixels
+}
+
+// Decodes one block into pixels stored row by row.
+fn decode_block(format: CompressedFormat, bytes: &[u8], out: &mut [[u8; 4]]) {
+    match format {
+        CompressedFormat::Bc1Rgb => bc1(bytes, false, out),
+        CompressedFormat::Bc1Rgba => bc1(bytes, true, out),
+        CompressedFormat::Bc2 => {
+            bc1(&bytes[8..], false, out);
+            for (i, pixel) in out.iter_mut().enumerate() {
+                let a = (bytes[i / 2] >> (i % 2 * 4)) & 0xf;
+                pixel[3] = a * 17;
+            }
+        }
+        CompressedFormat::Bc3 => {
+            bc1(&bytes[8..], false, out);
+            let alpha = bc4_channel(bytes);
+            for (pixel, a) in out.iter_mut().zip(alpha.iter()) {
+                pixel[3] = *a;
+            }
+        }
+        CompressedFormat::Bc4 => {
+            let red = bc4_channel(bytes);
+            for (pixel, r) in out.iter_mut().zip(red.iter()) {
+                *pixel = [*r, 0, 0, 255];
+            }
+        }
+        CompressedFormat::Bc5 => {
+            let red = bc4_channel(bytes);
+            let green = bc4_channel(&bytes[8..]);
+            for (i, pixel) in out.iter_mut().enumerate() {
+                *pixel = [red[i], green[i], 0, 255];
+            }
+        }
+        CompressedFormat::Etc2Rgb => etc2(bytes, out),
+        CompressedFormat::Astc(size) => {
+            astc::decode_block(bytes, [size[0] as usize, size[1] as usize], out)
+        }
+        CompressedFormat::Etc2Rgba => {
+            etc2(&bytes[8..], out);
+            let alpha = eac_alpha(bytes);
+            for (pixel, a) in out.iter_mut().zip(alpha.iter()) {
+                pixel[3] = *a;
+            }
+        }
+    }
+}
+
+// Expands a RGB565 color.
+fn rgb565(c: u16) -> [i32; 3] {
+    let r = (c >> 11) as i32 & 31;
+    let g = (c >> 5) as i32 & 63;
+    let b = c as i32 & 31;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+// Decodes a BC1 color block.
+//
+// When `alpha` is set, blocks with `c0 <= c1` use transparent black.
+fn bc1(bytes: &[u8], alpha: bool, out: &mut [[u8; 4]]) {
+    let c0 = u16::from(bytes[0]) | u16::from(bytes[1]) << 8;
+    let c1 = u16::from(bytes[2]) | u16::from(bytes[3]) << 8;
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mut palette = [[0u8; 4]; 4];
+    for k in 0..3 {
+        palette[0][k] = a[k] as u8;
+        palette[1][k] = b[k] as u8;
+        if c0 > c1 {
+            palette[2][k] = ((2 * a[k] + b[k]) / 3) as u8;
+            palette[3][k] = ((a[k] + 2 * b[k]) / 3) as u8;
+        } else {
+            palette[2][k] = ((a[k] + b[k]) / 2) as u8;
+        }
+    }
+    palette[0][3] = 255;
+    palette[1][3] = 255;
+    palette[2][3] = 255;
+    palette[3][3] = if c0 <= c1 && alpha { 0 } else { 255 };
+    let indices = u32::from(bytes[4]) | u32::from(bytes[5]) << 8 |
+        u32::from(bytes[6]) << 16 | u32::from(bytes[7]) << 24;
+    for (i, pixel) in out.iter_mut().enumerate() {
+        *pixel = palette[(indices >> (i * 2)) as usize & 3];
+    }
+}
+
+// Decodes a single channel block as used by BC3 alpha, BC4 and BC5.
+fn bc4_channel(bytes: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (u32::from(bytes[0]), u32::from(bytes[1]));
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for k in 1..7 {
+            palette[k + 1] = ((7 - k as u32) * a0 + k as u32 * a1) / 7;
+        }
+    } else {
+        for k in 1..5 {
+            palette[k + 1] = ((5 - k as u32) * a0 + k as u32 * a1) / 5;
+        }
+    }
+    let mut indices = 0u64;
+    for (k, &byte) in bytes[2..8].iter().enumerate() {
+        indices |= u64::from(byte) << (k * 8);
+    }
+    let mut out = [0; 16];
+    for (i, value) in out.iter_mut().enumerate() {
+        *value = palette[(indices >> (i * 3)) as usize & 7] as u8;
+    }
+    out
+}
+
+// Modifiers of ETC1 and ETC2 blocks, selected by table codeword.
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183],
+];
+
+// Distances of ETC2 T and H modes.
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+// Modifiers of EAC alpha blocks, selected by table index.
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn clamp(v: i32) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+// Reads bits `hi..=lo` of a block.
+fn bits(block: u64, hi: u32, lo: u32) -> i32 {
+    ((block >> lo) & ((1 << (hi - lo + 1)) - 1)) as i32
+}
+
+// Decodes an ETC2 RGB block, which includes ETC1 blocks.
+//
+// ETC pixel indices run column by column.
+fn etc2(bytes: &[u8], out: &mut [[u8; 4]]) {
+    let mut block = 0u64;
+    for &byte in &bytes[..8] {
+        block = block << 8 | u64::from(byte);
+    }
+    let index = |x: usize, y: usize| {
+        let i = x * 4 + y;
+        (bits(block, 16 + i as u32, 16 + i as u32) << 1) | bits(block, i as u32, i as u32)
+    };
+    let ext4 = |v: i32| v * 17;
+    let ext5 = |v: i32| (v << 3) | (v >> 2);
+    let rgb = |c: [i32; 3]| [clamp(c[0]), clamp(c[1]), clamp(c[2]), 255];
+    let offset = |c: [i32; 3], d: i32| rgb([c[0] + d, c[1] + d, c[2] + d]);
+
+    let diff = bits(block, 33, 33) == 1;
+    let r = bits(block, 63, 59) + (bits(block, 58, 56) << 29 >> 29);
+    let g = bits(block, 55, 51) + (bits(block, 50, 48) << 29 >> 29);
+    let b = bits(block, 47, 43) + (bits(block, 42, 40) << 29 >> 29);
+    if diff && !(0..=31).contains(&r) {
+        // T mode.
+        let c1 = [ext4((bits(block, 60, 59) << 2) | bits(block, 57, 56)),
+                  ext4(bits(block, 55, 52)), ext4(bits(block, 51, 48))];
+        let c2 = [ext4(bits(block, 47, 44)), ext4(bits(block, 43, 40)),
+                  ext4(bits(block, 39, 36))];
+        let d = ETC_DISTANCES[((bits(block, 35, 34) << 1) | bits(block, 32, 32)) as usize];
+        let paint = [rgb(c1), offset(c2, d), rgb(c2), offset(c2, -d)];
+        for y in 0..4 {
+            for x in 0..4 {
+                out[y * 4 + x] = paint[index(x, y) as usize];
+            }
+        }
+    } else if diff && !(0..=31).contains(&g) {
+        // H mode.
+        let c1 = [bits(block, 62, 59),
+                  (bits(block, 58, 56) << 1) | bits(block, 52, 52),
+                  (bits(block, 51, 51) << 3) | bits(block, 49, 47)];
+        let c2 = [bits(block, 46, 43), bits(block, 42, 39), bits(block, 38, 35)];
+        let order = |c: [i32; 3]| (c[0] << 8) | (c[1] << 4) | c[2];
+        let d = ETC_DISTANCES[((bits(block, 34, 34) << 2) | (bits(block, 32, 32) << 1) |
+            (order(c1) >= order(c2)) as i32) as usize];
+        let c1 = [ext4(c1[0]), ext4(c1[1]), ext4(c1[2])];
+        let c2 = [ext4(c2[0]), ext4(c2[1]), ext4(c2[2])];
+        let paint = [offset(c1, d), offset(c1, -d), offset(c2, d), offset(c2, -d)];
+        for y in 0..4 {
+            for x in 0..4 {
+                out[y * 4 + x] = paint[index(x, y) as usize];
+            }
+        }
+    } else if diff && !(0..=31).contains(&b) {
+        // Planar mode.
+        let ext6 = |v: i32| (v << 2) | (v >> 4);
+        let ext7 = |v: i32| (v << 1) | (v >> 6);
+        let o = [ext6(bits(block, 62, 57)),
+                 ext7((bits(block, 56, 56) << 6) | bits(block, 54, 49)),
+                 ext6((bits(block, 48, 48) << 5) | (bits(block, 44, 43) << 3) |
+                      bits(block, 41, 39))];
+        let h = [ext6((bits(block, 38, 34) << 1) | bits(block, 32, 32)),
+                 ext7(bits(block, 31, 25)), ext6(bits(block, 24, 19))];
+        let v = [ext6(bits(block, 18, 13)), ext7(bits(block, 12, 6)), ext6(bits(block, 5, 0))];
+        for y in 0..4 {
+            for x in 0..4 {
+                let (xi, yi) = (x as i32, y as i32);
+                let mut c = [0; 3];
+                for k in 0..3 {
+                    c[k] = (xi * (h[k] - o[k]) + yi * (v[k] - o[k]) + 4 * o[k] + 2) >> 2;
+                }
+                out[y * 4 + x] = rgb(c);
+            }
+        }
+    } else {
+        // Individual or differential mode with two sub-blocks.
+        let (c1, c2) = if diff {
+            let base = [bits(block, 63, 59), bits(block, 55, 51), bits(block, 47, 43)];
+            ([ext5(base[0]), ext5(base[1]), ext5(base[2])], [ext5(r), ext5(g), ext5(b)])
+        } else {
+            ([ext4(bits(block, 63, 60)), ext4(bits(block, 55, 52)), ext4(bits(block, 47, 44))],
+             [ext4(bits(block, 59, 56)), ext4(bits(block, 51, 48)), ext4(bits(block, 43, 40))])
+        };
+        let tables = [bits(block, 39, 37) as usize, bits(block, 36, 34) as usize];
+        let flip = bits(block, 32, 32) == 1;
+        for y in 0..4 {
+            for x in 0..4 {
+                let second = if flip { y >= 2 } else { x >= 2 };
+                let (c, table) = if second { (c2, tables[1]) } else { (c1, tables[0]) };
+                let modifier = ETC_MODIFIERS[table];
+                let d = match index(x, y) {
+                    0 => modifier[0],
+                    1 => modifier[1],
+                    2 => -modifier[0],
+                    _ => -modifier[1],
+                };
+                out[y * 4 + x] = offset(c, d);
+            }
+        }
+    }
+}
+
+// Decodes an EAC alpha block into 16 values stored row by row.
+fn eac_alpha(bytes: &[u8]) -> [u8; 16] {
+    let base = i32::from(bytes[0]);
+    let multiplier = i32::from(bytes[1] >> 4);
+    let modifiers = EAC_MODIFIERS[(bytes[1] & 0xf) as usize];
+    let mut indices = 0u64;
+    for &byte in &bytes[2..8] {
+        indices = indices << 8 | u64::from(byte);
+    }
+    let mut out = [0; 16];
+    for y in 0..4 {
+        for x in 0..4 {
+            // Indices run column by column from the most significant bits.
+            let i = x * 4 + y;
+            let index = (indices >> (45 - i * 3)) as usize & 7;
+            out[y * 4 + x] = clamp(base + modifiers[index] * multiplier);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the RGBA pixel at an index of a decoded 4x4 block.
+    fn pixel(pixels: &[u8], i: usize) -> [u8; 4] {
+        [pixels[i * 4], pixels[i * 4 + 1], pixels[i * 4 + 2], pixels[i * 4 + 3]]
+    }
+
+    #[test]
+    fn bc1_interpolates_four_colors() {
+        // Red and blue endpoints, with indices 0 to 3 in the first pixels.
+        let bytes = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0, 0, 0];
+        let pixels = decode(CompressedFormat::Bc1Rgb, [4, 4], &bytes);
+        assert_eq!(pixels.len(), 64);
+        assert_eq!(pixel(&pixels, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 1), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 2), [170, 0, 85, 255]);
+        assert_eq!(pixel(&pixels, 3), [85, 0, 170, 255]);
+        assert_eq!(pixel(&pixels, 15), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc1_with_alpha_uses_transparent_black() {
+        // Blue and red endpoints in three color mode, with index 3 in the first pixel.
+        let bytes = [0x1f, 0x00, 0x00, 0xf8, 0x03, 0, 0, 0];
+        let pixels = decode(CompressedFormat::Bc1Rgba, [4, 4], &bytes);
+        assert_eq!(pixel(&pixels, 0), [0, 0, 0, 0]);
+        let pixels = decode(CompressedFormat::Bc1Rgb, [4, 4], &bytes);
+        assert_eq!(pixel(&pixels, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 1), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn bc3_decodes_interpolated_alpha() {
+        // Alpha from 255 to 0 with indices 0, 1 and 2, on a red color block.
+        let bytes = [
+            255, 0, 0x88, 0, 0, 0, 0, 0,
+            0x00, 0xf8, 0x1f, 0x00, 0, 0, 0, 0,
+        ];
+        let pixels = decode(CompressedFormat::Bc3, [4, 4], &bytes);
+        assert_eq!(pixel(&pixels, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 1), [255, 0, 0, 0]);
+        assert_eq!(pixel(&pixels, 2), [255, 0, 0, 218]);
+        assert_eq!(pixel(&pixels, 3), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc4_decodes_red_with_explicit_extremes() {
+        // Endpoints in the mode with explicit 0 and 255, using indices 6, 7 and 2.
+        let bytes = [0, 255, 0xbe, 0, 0, 0, 0, 0];
+        let pixels = decode(CompressedFormat::Bc4, [4, 4], &bytes);
+        assert_eq!(pixel(&pixels, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 2), [51, 0, 0, 255]);
+    }
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        let bytes = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0, 0, 0];
+        let pixels = decode(CompressedFormat::Bc1Rgb, [2, 1], &bytes);
+        assert_eq!(pixels, vec![255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+}
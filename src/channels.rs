@@ -0,0 +1,95 @@
+//! Textures with one or two channels per pixel.
+
+use gfx;
+use gfx::format::{ChannelType, SurfaceType};
+
+use compressed::create_texture;
+use {Error, Texture, TextureContext, TextureSettings};
+
+/// How the channels of single and two channel textures are drawn.
+///
+/// `GfxGraphics::tri_list_uv` and `GfxGraphics::tri_list_uv_c` select the swizzle
+/// by texture format, drawing alpha textures as white with alpha,
+/// so they are tinted by the color like masks and glyphs.
+/// Signed distance field draw calls sample the raw channels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelLayout {
+    /// One byte of sRGB luminance per pixel, drawn opaque.
+    ///
+    /// Stored like `LuminanceAlpha` with full alpha.
+    Luminance,
+    /// One byte of alpha per pixel, stored in an R8 texture.
+    Alpha,
+    /// Two bytes of sRGB luminance and alpha per pixel, stored in an R8_G8 texture.
+    LuminanceAlpha,
+}
+
+impl ChannelLayout {
+    /// Returns the number of bytes per pixel of the layout.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match *self {
+            ChannelLayout::Luminance | ChannelLayout::Alpha => 1,
+            ChannelLayout::LuminanceAlpha => 2,
+        }
+    }
+}
+
+/// Creates textures from single and two channel pixels.
+///
+/// Drawing the textures requires `Gfx2d::enable_channels`.
+/// The textures can not be updated with `Texture::update`,
+/// which fails with `UpdateError::InvalidUsage`.
+pub trait ChannelTexture<R: gfx::Resources>: Sized {
+    /// Creates a texture from pixels with a channel layout.
+    fn from_channels<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        memory: &[u8],
+        size: [u32; 2],
+        layout: ChannelLayout,
+        settings: &TextureSettings
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>;
+}
+
+impl<R: gfx::Resources> ChannelTexture<R> for Texture<R> {
+    fn from_channels<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        memory: &[u8],
+        size: [u32; 2],
+        layout: ChannelLayout,
+        settings: &TextureSettings
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        if size[0] == 0 || size[1] == 0 {
+            return Texture::empty(context);
+        }
+        let expected = size[0] as usize * size[1] as usize * layout.bytes_per_pixel();
+        if memory.len() != expected {
+            return Err(Error::Image(format!(
+                "Expected {} bytes for {:?} texture of size {:?}, got {}",
+                expected, layout, size, memory.len())));
+        }
+        let factory = &mut context.factory;
+        let one_channel = (SurfaceType::R8, ChannelType::Unorm);
+        let two_channels = (SurfaceType::R8_G8, ChannelType::Unorm);
+        match layout {
+            ChannelLayout::Alpha => {
+                create_texture(factory, one_channel, size, &[memory], settings)
+            }
+            ChannelLayout::LuminanceAlpha => {
+                create_texture(factory, two_channels, size, &[memory], settings)
+            }
+            ChannelLayout::Luminance => {
+                let mut luminance_alpha = Vec::with_capacity(memory.len() * 2);
+                for &l in memory {
+                    luminance_alpha.push(l);
+                    luminance_alpha.push(255);
+                }
+                create_texture(factory, two_channels, size, &[&luminance_alpha], settings)
+            }
+        }
+    }
+}
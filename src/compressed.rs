@@ -0,0 +1,496 @@
+//! Block-compressed textures loaded from KTX and DDS containers.
+//!
+//! BC1 to BC5, ETC2 and LDR ASTC are supported.
+//! BC6H and BC7 are not: there is no surface type for them in gfx,
+//! nor a decoder here, so loading them fails with an unsupported format error.
+
+use std::fs;
+use std::path::Path;
+
+use gfx;
+use gfx::format::{ChannelType, SurfaceType, Swizzle};
+use gfx::memory::{Bind, Typed, Usage};
+use gfx::texture::{AaMode, Info, Kind, Mipmap, ResourceDesc};
+use image::RgbaImage;
+
+use block;
+use sampler::{mipmap_sampler_info, sampler_info};
+use {Error, Texture, TextureContext, TextureSettings};
+
+/// Block compression formats of compressed images.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressedFormat {
+    /// BC1 (DXT1) without alpha.
+    Bc1Rgb,
+    /// BC1 (DXT1) with 1-bit alpha.
+    Bc1Rgba,
+    /// BC2 (DXT3) with explicit 4-bit alpha.
+    Bc2,
+    /// BC3 (DXT5) with interpolated alpha.
+    Bc3,
+    /// BC4 with one red channel.
+    Bc4,
+    /// BC5 with red and green channels.
+    Bc5,
+    /// ETC2 RGB, which also decodes ETC1.
+    Etc2Rgb,
+    /// ETC2 RGB with EAC alpha.
+    Etc2Rgba,
+    /// ASTC with low dynamic range and a block size of `[width, height]` pixels.
+    ///
+    /// Always decompressed on the CPU, since gfx has no ASTC surface type.
+    Astc([u32; 2]),
+}
+
+// ASTC block sizes in the order of their OpenGL internal formats.
+const ASTC_BLOCK_SIZES: [[u32; 2]; 14] = [
+    [4, 4], [5, 4], [5, 5], [6, 5], [6, 6], [8, 5], [8, 6],
+    [8, 8], [10, 5], [10, 6], [10, 8], [10, 10], [12, 10], [12, 12],
+];
+
+impl CompressedFormat {
+    /// Returns the size of a block in pixels.
+    pub fn block_size(&self) -> [u32; 2] {
+        match *self {
+            CompressedFormat::Astc(size) => size,
+            _ => [4, 4],
+        }
+    }
+
+    /// Returns the number of bytes of a block.
+    pub fn block_bytes(&self) -> usize {
+        match *self {
+            CompressedFormat::Bc1Rgb |
+            CompressedFormat::Bc1Rgba |
+            CompressedFormat::Bc4 |
+            CompressedFormat::Etc2Rgb => 8,
+            CompressedFormat::Bc2 |
+            CompressedFormat::Bc3 |
+            CompressedFormat::Bc5 |
+            CompressedFormat::Etc2Rgba |
+            CompressedFormat::Astc(_) => 16,
+        }
+    }
+
+    // Returns the gfx surface type, if gfx can upload the format.
+    fn surface_type(&self) -> Option<SurfaceType> {
+        match *self {
+            CompressedFormat::Bc1Rgb => Some(SurfaceType::BC1_R8_G8_B8),
+            CompressedFormat::Bc3 => Some(SurfaceType::BC3_R8_G8_B8_A8),
+            _ => None,
+        }
+    }
+
+    // Returns the format of an OpenGL internal format used by KTX.
+    fn from_gl(internal_format: u32) -> Result<CompressedFormat, Error> {
+        Ok(match internal_format {
+            0x83F0 | 0x8C4C => CompressedFormat::Bc1Rgb,
+            0x83F1 | 0x8C4D => CompressedFormat::Bc1Rgba,
+            0x83F2 | 0x8C4E => CompressedFormat::Bc2,
+            0x83F3 | 0x8C4F => CompressedFormat::Bc3,
+            0x8DBB => CompressedFormat::Bc4,
+            0x8DBD => CompressedFormat::Bc5,
+            0x8D64 | 0x9274 | 0x9275 => CompressedFormat::Etc2Rgb,
+            0x9278 | 0x9279 => CompressedFormat::Etc2Rgba,
+            0x93B0..=0x93BD => CompressedFormat::Astc(ASTC_BLOCK_SIZES[(internal_format - 0x93B0) as usize]),
+            0x93D0..=0x93DD => CompressedFormat::Astc(ASTC_BLOCK_SIZES[(internal_format - 0x93D0) as usize]),
+            _ => {
+                return Err(Error::Image(format!(
+                    "Unsupported KTX internal format 0x{:X}", internal_format)));
+            }
+        })
+    }
+
+    // Returns the format of a DXGI format used by DDS.
+    fn from_dxgi(dxgi_format: u32) -> Result<CompressedFormat, Error> {
+        Ok(match dxgi_format {
+            71 | 72 => CompressedFormat::Bc1Rgba,
+            74 | 75 => CompressedFormat::Bc2,
+            77 | 78 => CompressedFormat::Bc3,
+            80 => CompressedFormat::Bc4,
+            83 => CompressedFormat::Bc5,
+            _ => {
+                return Err(Error::Image(format!(
+                    "Unsupported DDS DXGI format {}", dxgi_format)));
+            }
+        })
+    }
+}
+
+/// Block-compressed pixels with mip levels.
+///
+/// Loaded from KTX (version 1) or DDS containers holding 2D textures.
+#[derive(Clone, Debug)]
+pub struct CompressedImage {
+    format: CompressedFormat,
+    size: [u32; 2],
+    levels: Vec<Vec<u8>>,
+}
+
+// Identifier at the start of KTX files.
+const KTX_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A
+];
+
+// Identifier at the start of DDS files.
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+// DDS pixel format flag set when the image has alpha.
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+
+impl CompressedImage {
+    /// Creates a new image from blocks of each mip level.
+    pub fn new(
+        format: CompressedFormat,
+        size: [u32; 2],
+        levels: Vec<Vec<u8>>
+    ) -> Result<CompressedImage, Error> {
+        if size[0] == 0 || size[1] == 0 || levels.is_empty() {
+            return Err(Error::Image("Compressed image can not be empty".into()));
+        }
+        texture_size(size)?;
+        check_levels(size, levels.len())?;
+        for (i, level) in levels.iter().enumerate() {
+            let expected = level_bytes(format, level_size(size, i));
+            if level.len() < expected {
+                return Err(Error::Image(format!(
+                    "Expected {} bytes for mip level {}, got {}", expected, i, level.len())));
+            }
+        }
+        Ok(CompressedImage {
+            format,
+            size,
+            levels,
+        })
+    }
+
+    /// Loads a KTX or DDS file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<CompressedImage, Error> {
+        let bytes = fs::read(path).map_err(|err| Error::Image(err.to_string()))?;
+        CompressedImage::from_memory(&bytes)
+    }
+
+    /// Parses a KTX or DDS container, detected by its identifier.
+    pub fn from_memory(bytes: &[u8]) -> Result<CompressedImage, Error> {
+        if bytes.starts_with(&KTX_IDENTIFIER) {
+            CompressedImage::from_ktx(bytes)
+        } else if bytes.starts_with(DDS_MAGIC) {
+            CompressedImage::from_dds(bytes)
+        } else {
+            Err(Error::Image("Expected a KTX or DDS container".into()))
+        }
+    }
+
+    /// Parses a KTX container.
+    pub fn from_ktx(bytes: &[u8]) -> Result<CompressedImage, Error> {
+        if !bytes.starts_with(&KTX_IDENTIFIER) || bytes.len() < 64 {
+            return Err(Error::Image("Invalid KTX header".into()));
+        }
+        let big_endian = read_u32(bytes, 12, false)? != 0x04030201;
+        let field = |i: usize| read_u32(bytes, 16 + i * 4, big_endian);
+        let internal_format = field(3)?;
+        let size = [field(5)?, field(6)?];
+        if field(7)? > 1 || field(8)? > 0 || field(9)? > 1 {
+            return Err(Error::Image("Only 2D KTX textures are supported".into()));
+        }
+        let format = CompressedFormat::from_gl(internal_format)?;
+        texture_size(size)?;
+        let level_count = field(10)?.max(1) as usize;
+        check_levels(size, level_count)?;
+        let mut offset = 64 + field(11)? as usize;
+        let mut levels = Vec::with_capacity(level_count);
+        for _ in 0..level_count {
+            let image_size = read_u32(bytes, offset, big_endian)? as usize;
+            let data = read_bytes(bytes, offset + 4, image_size)?;
+            levels.push(data.to_vec());
+            // Levels are padded to 4 bytes.
+            offset += 4 + image_size.div_ceil(4) * 4;
+        }
+        CompressedImage::new(format, size, levels)
+    }
+
+    /// Parses a DDS container.
+    pub fn from_dds(bytes: &[u8]) -> Result<CompressedImage, Error> {
+        if !bytes.starts_with(DDS_MAGIC) || bytes.len() < 128 {
+            return Err(Error::Image("Invalid DDS header".into()));
+        }
+        let flags = read_u32(bytes, 8, false)?;
+        let size = [read_u32(bytes, 16, false)?, read_u32(bytes, 12, false)?];
+        // Only use the mip map count when flagged as valid.
+        let level_count = if flags & 0x20000 != 0 {
+            read_u32(bytes, 28, false)?.max(1) as usize
+        } else {
+            1
+        };
+        texture_size(size)?;
+        check_levels(size, level_count)?;
+        let alpha = read_u32(bytes, 80, false)? & DDPF_ALPHAPIXELS != 0;
+        let (format, mut offset) = match read_bytes(bytes, 84, 4)? {
+            // DXT1 is only drawn with alpha when flagged,
+            // which keeps it compressed on the GPU otherwise.
+            b"DXT1" if alpha => (CompressedFormat::Bc1Rgba, 128),
+            b"DXT1" => (CompressedFormat::Bc1Rgb, 128),
+            b"DXT2" | b"DXT3" => (CompressedFormat::Bc2, 128),
+            b"DXT4" | b"DXT5" => (CompressedFormat::Bc3, 128),
+            b"ATI1" | b"BC4U" => (CompressedFormat::Bc4, 128),
+            b"ATI2" | b"BC5U" => (CompressedFormat::Bc5, 128),
+            b"DX10" => {
+                if read_u32(bytes, 132, false)? != 3 {
+                    return Err(Error::Image("Only 2D DDS textures are supported".into()));
+                }
+                (CompressedFormat::from_dxgi(read_u32(bytes, 128, false)?)?, 148)
+            }
+            four_cc => {
+                return Err(Error::Image(format!(
+                    "Unsupported DDS format {:?}", String::from_utf8_lossy(four_cc))));
+            }
+        };
+        let mut levels = Vec::with_capacity(level_count);
+        for i in 0..level_count {
+            let len = level_bytes(format, level_size(size, i));
+            levels.push(read_bytes(bytes, offset, len)?.to_vec());
+            offset += len;
+        }
+        CompressedImage::new(format, size, levels)
+    }
+
+    /// Returns the compression format.
+    pub fn format(&self) -> CompressedFormat {
+        self.format
+    }
+
+    /// Returns the size of the first level in pixels.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Returns the number of mip levels.
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Decompresses a mip level into RGBA pixels.
+    ///
+    /// Panics if `level` is not less than `levels()`.
+    pub fn decompress(&self, level: usize) -> RgbaImage {
+        let size = level_size(self.size, level);
+        let pixels = block::decode(self.format, size, &self.levels[level]);
+        RgbaImage::from_raw(size[0], size[1], pixels)
+            .expect("Decoded pixels should match the level size")
+    }
+}
+
+/// Creates textures from compressed images.
+///
+/// Formats supported by gfx (`Bc1Rgb` and `Bc3`) are uploaded compressed.
+/// Other formats are decompressed to RGBA on the CPU,
+/// as is everything when `decompress` is set.
+/// Set `decompress` when the device lacks S3TC support,
+/// which gfx can not detect.
+///
+/// The mip levels of the image are uploaded with the texture,
+/// and sampled as described by `MipmapTexture` when there are several.
+/// Compressed textures can not be updated with `Texture::update`,
+/// which fails with `UpdateError::InvalidUsage`.
+pub trait CompressedTexture<R: gfx::Resources>: Sized {
+    /// Creates a texture from a compressed image.
+    fn from_compressed<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        image: &CompressedImage,
+        settings: &TextureSettings,
+        decompress: bool
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>;
+
+    /// Creates a texture from a KTX or DDS file.
+    fn from_compressed_path<F, C, P>(
+        context: &mut TextureContext<F, R, C>,
+        path: P,
+        settings: &TextureSettings,
+        decompress: bool
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>,
+              P: AsRef<Path>
+    {
+        let image = CompressedImage::from_path(path)?;
+        Self::from_compressed(context, &image, settings, decompress)
+    }
+}
+
+impl<R: gfx::Resources> CompressedTexture<R> for Texture<R> {
+    fn from_compressed<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        image: &CompressedImage,
+        settings: &TextureSettings,
+        decompress: bool
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        let factory = &mut context.factory;
+        let surface_type = if decompress { None } else { image.format.surface_type() };
+        match surface_type {
+            Some(surface_type) => {
+                let levels: Vec<&[u8]> = image.levels.iter().map(|level| &level[..]).collect();
+                create_texture(factory, (surface_type, ChannelType::Srgb), image.size,
+                               &levels, settings)
+            }
+            None => {
+                let decompressed: Vec<RgbaImage> = (0..image.levels())
+                    .map(|level| image.decompress(level)).collect();
+                let levels: Vec<&[u8]> = decompressed.iter()
+                    .map(|level| level.as_raw().as_slice()).collect();
+                create_texture(factory, (SurfaceType::R8_G8_B8_A8, ChannelType::Srgb),
+                               image.size, &levels, settings)
+            }
+        }
+    }
+}
+
+/// Creates a texture of any surface format with all mip levels provided.
+///
+/// The surface keeps the RGBA type of `Texture`,
+/// while the raw texture info describes the actual format.
+/// Only RGBA surfaces can be updated, so updates through the RGBA type
+/// fail with `UpdateError::InvalidUsage` for other formats
+/// instead of writing pixels of the wrong format.
+pub(crate) fn create_texture<F, R>(
+    factory: &mut F,
+    (surface_type, channel): (SurfaceType, ChannelType),
+    size: [u32; 2],
+    levels: &[&[u8]],
+    settings: &TextureSettings
+) -> Result<Texture<R>, Error>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources
+{
+    let (width, height) = texture_size(size)?;
+    check_levels(size, levels.len())?;
+    let count = levels.len() as u8;
+    let usage = if surface_type == SurfaceType::R8_G8_B8_A8 { Usage::Dynamic } else { Usage::Data };
+    let info = Info {
+        kind: Kind::D2(width, height, AaMode::Single),
+        levels: count,
+        format: surface_type,
+        bind: Bind::SHADER_RESOURCE,
+        usage,
+    };
+    let raw = factory.create_texture_raw(info, Some(channel),
+                                         Some((levels, Mipmap::Provided)))?;
+    let desc = ResourceDesc {
+        channel,
+        layer: None,
+        min: 0,
+        max: count - 1,
+        swizzle: Swizzle::new(),
+    };
+    let view = factory.view_texture_as_shader_resource_raw(&raw, desc)?;
+    let sampler = if count > 1 {
+        factory.create_sampler(mipmap_sampler_info(settings))
+    } else {
+        factory.create_sampler(sampler_info(settings))
+    };
+    Ok(Texture { surface: Typed::new(raw), sampler, view: Typed::new(view) })
+}
+
+/// Returns the size of a texture as gfx dimensions.
+///
+/// Fails for empty sizes and sizes larger than gfx supports.
+pub(crate) fn texture_size(size: [u32; 2]) -> Result<(u16, u16), Error> {
+    if size[0] == 0 || size[1] == 0 || size[0] > u16::MAX as u32 || size[1] > u16::MAX as u32 {
+        return Err(Error::Image(format!(
+            "Texture size {:?} must be between 1 and {} pixels", size, u16::MAX)));
+    }
+    Ok((size[0] as u16, size[1] as u16))
+}
+
+// Returns the number of levels of a full mip chain.
+fn max_levels(size: [u32; 2]) -> usize {
+    (32 - size[0].max(size[1]).leading_zeros()) as usize
+}
+
+// Fails when there are more mip levels than the size allows.
+fn check_levels(size: [u32; 2], count: usize) -> Result<(), Error> {
+    if count == 0 || count > max_levels(size) {
+        return Err(Error::Image(format!(
+            "Invalid mip level count {} for size {:?}", count, size)));
+    }
+    Ok(())
+}
+
+// Returns the size of a mip level.
+fn level_size(size: [u32; 2], level: usize) -> [u32; 2] {
+    let shift = level.min(31) as u32;
+    [(size[0] >> shift).max(1), (size[1] >> shift).max(1)]
+}
+
+// Returns the number of bytes of all blocks in a level.
+fn level_bytes(format: CompressedFormat, size: [u32; 2]) -> usize {
+    let [bw, bh] = format.block_size();
+    let blocks = size[0].div_ceil(bw) as usize * size[1].div_ceil(bh) as usize;
+    blocks * format.block_bytes()
+}
+
+fn read_bytes(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    offset.checked_add(len)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| Error::Image("Unexpected end of compressed texture data".into()))
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Result<u32, Error> {
+    let b = read_bytes(bytes, offset, 4)?;
+    let b = [b[0], b[1], b[2], b[3]];
+    Ok(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns a DDS header of a 4x4 BC1 image.
+    fn dds_header(mip_count: u32) -> Vec<u8> {
+        let mut bytes = vec![0; 128];
+        bytes[..4].copy_from_slice(DDS_MAGIC);
+        bytes[8..12].copy_from_slice(&0x20000u32.to_le_bytes());
+        bytes[12..16].copy_from_slice(&4u32.to_le_bytes());
+        bytes[16..20].copy_from_slice(&4u32.to_le_bytes());
+        bytes[28..32].copy_from_slice(&mip_count.to_le_bytes());
+        bytes[84..88].copy_from_slice(b"DXT1");
+        bytes
+    }
+
+    #[test]
+    fn dds_levels_are_read() {
+        let mut bytes = dds_header(3);
+        bytes.extend_from_slice(&[0; 3 * 8]);
+        let image = CompressedImage::from_dds(&bytes).unwrap();
+        assert_eq!(image.format(), CompressedFormat::Bc1Rgb);
+        assert_eq!(image.size(), [4, 4]);
+        assert_eq!(image.levels(), 3);
+    }
+
+    #[test]
+    fn dds_dxt1_has_alpha_only_when_flagged() {
+        let mut bytes = dds_header(1);
+        bytes[80..84].copy_from_slice(&DDPF_ALPHAPIXELS.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        let image = CompressedImage::from_dds(&bytes).unwrap();
+        assert_eq!(image.format(), CompressedFormat::Bc1Rgba);
+    }
+
+    #[test]
+    fn huge_dds_mip_count_is_rejected() {
+        let mut bytes = dds_header(u32::MAX);
+        bytes.extend_from_slice(&[0; 8]);
+        assert!(CompressedImage::from_dds(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated_dds_data_is_rejected() {
+        let mut bytes = dds_header(1);
+        bytes.extend_from_slice(&[0; 4]);
+        assert!(CompressedImage::from_dds(&bytes).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Debug overlays for finding what breaks batching.
+
+use gfx;
+
+use RenderTarget;
+
+// Colors of consecutive batches, repeated when there are more batches.
+const PALETTE: [[f32; 3]; 8] = [
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 1.0],
+    [1.0, 0.0, 1.0],
+    [1.0, 0.5, 0.0],
+    [0.5, 0.0, 1.0],
+];
+
+// Opacity of batch tints.
+const TINT_ALPHA: f32 = 0.5;
+
+// Color added per draw in the overdraw heatmap.
+//
+// Goes from red through yellow to white as overlapping draws add up.
+const HEAT: [f32; 4] = [0.25, 0.0625, 0.015625, 1.0];
+
+/// A debug overlay drawn by `Gfx2d` on top of normal rendering.
+///
+/// Every flushed batch is one draw call,
+/// so the overlay shows where `tri_list` and `tri_list_uv` start new batches.
+pub enum DebugOverlay<R: gfx::Resources> {
+    /// Tints each flushed batch in a different color.
+    BatchTint,
+    /// Outlines the bounding box of each flushed batch in a different color.
+    BatchBounds,
+    /// Renders an overdraw heatmap into a target, then draws it over the output.
+    ///
+    /// Each draw adds to the pixels it covers with additive blending,
+    /// ignoring clipping by the stencil buffer.
+    /// The target should be single sampled and match the size of the output.
+    Overdraw(RenderTarget<R>),
+}
+
+/// The state of a debug overlay while drawing.
+pub(crate) struct DebugState<R: gfx::Resources> {
+    pub(crate) overlay: DebugOverlay<R>,
+    /// Number of batches drawn in the current frame.
+    batches: usize,
+    /// Bounding box `[x0, y0, x1, y1]` of the current batch in normalized coordinates.
+    bounds: Option<[f32; 4]>,
+}
+
+impl<R: gfx::Resources> DebugState<R> {
+    pub(crate) fn new(overlay: DebugOverlay<R>) -> DebugState<R> {
+        DebugState {
+            overlay,
+            batches: 0,
+            bounds: None,
+        }
+    }
+
+    /// Starts a new frame.
+    pub(crate) fn begin_frame(&mut self) {
+        self.batches = 0;
+        self.bounds = None;
+    }
+
+    /// Includes vertices in the bounding box of the current batch.
+    pub(crate) fn add_vertices(&mut self, vertices: &[[f32; 2]]) {
+        if let DebugOverlay::BatchBounds = self.overlay {
+            for v in vertices {
+                let b = self.bounds.get_or_insert([v[0], v[1], v[0], v[1]]);
+                *b = [b[0].min(v[0]), b[1].min(v[1]), b[2].max(v[0]), b[3].max(v[1])];
+            }
+        }
+    }
+
+    /// Finishes the current batch, returning its color and bounding box.
+    pub(crate) fn next_batch(&mut self) -> ([f32; 4], Option<[f32; 4]>) {
+        let [r, g, b] = PALETTE[self.batches % PALETTE.len()];
+        self.batches += 1;
+        let alpha = match self.overlay {
+            DebugOverlay::BatchTint => TINT_ALPHA,
+            DebugOverlay::BatchBounds => 1.0,
+            DebugOverlay::Overdraw(_) => return (HEAT, self.bounds.take()),
+        };
+        ([r, g, b, alpha], self.bounds.take())
+    }
+}
+
+/// Returns triangles outlining a bounding box `[x0, y0, x1, y1]`.
+///
+/// The outline is `[w, h]` thick in normalized coordinates.
+pub(crate) fn outline(bounds: [f32; 4], [w, h]: [f32; 2]) -> [[f32; 2]; 24] {
+    let [x0, y0, x1, y1] = bounds;
+    let rects = [
+        [x0, y0, x1, y0 + h],
+        [x0, y1 - h, x1, y1],
+        [x0, y0, x0 + w, y1],
+        [x1 - w, y0, x1, y1],
+    ];
+    let mut vertices = [[0.0; 2]; 24];
+    for (quad, r) in vertices.chunks_mut(6).zip(rects.iter()) {
+        quad.copy_from_slice(&[
+            [r[0], r[1]], [r[2], r[1]], [r[0], r[3]],
+            [r[2], r[1]], [r[2], r[3]], [r[0], r[3]],
+        ]);
+    }
+    vertices
+}
@@ -0,0 +1,187 @@
+//! Textures updated every frame.
+
+use gfx;
+use gfx::format::Rgba8;
+use image::RgbaImage;
+
+use back_end::GfxGraphics;
+use texture_region::contains;
+use {Error, ImageSize, Texture, TextureContext, TextureSettings};
+
+/// A texture for streaming pixels, e.g. video frames or generated canvases.
+///
+/// Regions are written with `update_region` through the encoder of `GfxGraphics`,
+/// so they are uploaded in order with drawing commands.
+///
+/// When double-buffered, updates are written to a back texture
+/// while the front texture returned by `texture` can still be in use by the GPU.
+/// Call `swap` to show the updates.
+/// A copy of the pixels is kept on the CPU,
+/// so regions written to one texture are also written to the other after swapping.
+pub struct DynamicTexture<R: gfx::Resources> {
+    textures: Vec<Texture<R>>,
+    // Index of the texture returned by `texture`.
+    front: usize,
+    size: [u32; 2],
+    // Latest pixels, only kept when double-buffered.
+    pixels: Option<RgbaImage>,
+    // Regions written to the back texture since the last swap.
+    written: Vec<[u32; 4]>,
+    // Regions the back texture is missing from before the last swap.
+    stale: Vec<[u32; 4]>,
+}
+
+impl<R: gfx::Resources> DynamicTexture<R> {
+    /// Creates a new transparent texture.
+    pub fn new<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        size: [u32; 2],
+        double_buffered: bool,
+        settings: &TextureSettings
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        if size[0] == 0 || size[1] == 0 {
+            return Err(Error::Image("Dynamic texture can not be empty".into()));
+        }
+        let pixels = RgbaImage::new(size[0], size[1]);
+        let count = if double_buffered { 2 } else { 1 };
+        let mut textures = Vec::with_capacity(count);
+        for _ in 0..count {
+            textures.push(Texture::from_image(context, &pixels, settings)?);
+        }
+        Ok(DynamicTexture {
+            textures,
+            front: 0,
+            size,
+            pixels: if double_buffered { Some(pixels) } else { None },
+            written: vec![],
+            stale: vec![],
+        })
+    }
+
+    /// Returns true if updates are written to a separate back texture.
+    pub fn is_double_buffered(&self) -> bool {
+        self.textures.len() == 2
+    }
+
+    /// Returns the texture to draw.
+    pub fn texture(&self) -> &Texture<R> {
+        &self.textures[self.front]
+    }
+
+    /// Writes RGBA pixels into a rectangle `[x, y, w, h]`.
+    ///
+    /// Flushes batched triangles first, so earlier draw calls
+    /// of a single-buffered texture use the old pixels.
+    pub fn update_region<C>(
+        &mut self,
+        g: &mut GfxGraphics<R, C>,
+        rect: [u32; 4],
+        pixels: &[u8]
+    ) -> Result<(), Error>
+        where C: gfx::CommandBuffer<R>
+    {
+        if !contains(self.size, rect) {
+            return Err(Error::Image(format!(
+                "Region {:?} is outside dynamic texture of size {:?}", rect, self.size)));
+        }
+        let expected = rect[2] as usize * rect[3] as usize * 4;
+        if pixels.len() != expected {
+            return Err(Error::Image(format!(
+                "Expected {} bytes for region {:?}, got {}", expected, rect, pixels.len())));
+        }
+        if rect[2] == 0 || rect[3] == 0 {
+            return Ok(());
+        }
+
+        g.flush();
+        let back = (self.front + 1) % self.textures.len();
+        let surface = &self.textures[back].surface;
+        if let Some(ref mut image) = self.pixels {
+            // Bring the back texture up to date before writing the new region.
+            for stale in self.stale.drain(..) {
+                let memory = copy_rect(image, stale);
+                update_texture(g.encoder, surface, stale, &memory)?;
+            }
+            let memory: &mut [u8] = image;
+            let width = rect[2] as usize * 4;
+            for (y, row) in pixels.chunks(width).enumerate() {
+                let start = (((rect[1] as usize + y) * self.size[0] as usize)
+                    + rect[0] as usize) * 4;
+                memory[start..start + width].copy_from_slice(row);
+            }
+            self.written.push(rect);
+        }
+        update_texture(g.encoder, surface, rect, pixels)
+    }
+
+    /// Shows the updates written since the last swap.
+    ///
+    /// Does nothing when single-buffered or when nothing was written,
+    /// so the front texture is always up to date after swapping.
+    pub fn swap(&mut self) {
+        if self.is_double_buffered() && !self.written.is_empty() {
+            self.front = 1 - self.front;
+            self.stale.append(&mut self.written);
+        }
+    }
+}
+
+impl<R: gfx::Resources> ImageSize for DynamicTexture<R> {
+    fn get_size(&self) -> (u32, u32) {
+        (self.size[0], self.size[1])
+    }
+}
+
+// Copies the pixels of a rectangle into a new buffer.
+fn copy_rect(image: &RgbaImage, rect: [u32; 4]) -> Vec<u8> {
+    let stride = image.width() as usize * 4;
+    let width = rect[2] as usize * 4;
+    let mut memory = Vec::with_capacity(width * rect[3] as usize);
+    for y in rect[1]..rect[1] + rect[3] {
+        let start = y as usize * stride + rect[0] as usize * 4;
+        memory.extend_from_slice(&image.as_raw()[start..start + width]);
+    }
+    memory
+}
+
+// Writes pixels into a rectangle of a texture.
+fn update_texture<R, C>(
+    encoder: &mut gfx::Encoder<R, C>,
+    surface: &gfx::handle::Texture<R, gfx::format::R8_G8_B8_A8>,
+    rect: [u32; 4],
+    memory: &[u8]
+) -> Result<(), Error>
+    where R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    let img_info = gfx::texture::ImageInfoCommon {
+        xoffset: rect[0] as u16,
+        yoffset: rect[1] as u16,
+        zoffset: 0,
+        width: rect[2] as u16,
+        height: rect[3] as u16,
+        depth: 0,
+        format: (),
+        mipmap: 0,
+    };
+    let data = gfx::memory::cast_slice(memory);
+    encoder.update_texture::<_, Rgba8>(surface, None, img_info, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::contains;
+
+    #[test]
+    fn regions_outside_are_rejected_without_overflow() {
+        assert!(contains([4, 4], [0, 0, 4, 4]));
+        assert!(contains([4, 4], [4, 4, 0, 0]));
+        assert!(!contains([4, 4], [1, 0, 4, 4]));
+        assert!(!contains([4, 4], [u32::MAX, 0, 2, 1]));
+        assert!(!contains([4, 4], [0, 2, 1, u32::MAX]));
+    }
+}
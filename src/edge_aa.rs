@@ -0,0 +1,195 @@
+//! Analytic anti-aliasing of colored triangles.
+//!
+//! Finds the outer edges of a triangle list and adds a fringe of triangles
+//! around them, fading from the vertex color to full transparency.
+//! This smooths edges on single sampled targets when alpha blending is used.
+
+use std::collections::HashMap;
+
+// The width of the fringe in pixels.
+const FRINGE_WIDTH: f32 = 1.0;
+// Limits the length of miter offsets at sharp corners.
+const MAX_MITER: f32 = 4.0;
+
+// Positions are matched after snapping to this many steps per pixel,
+// so edges shared by triangles of different chunks match despite rounding.
+const SNAP: f32 = 64.0;
+
+type VertexKey = [i32; 2];
+
+fn edge_key(a: VertexKey, b: VertexKey) -> (VertexKey, VertexKey) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+fn normalize(v: [f32; 2]) -> Option<[f32; 2]> {
+    let len = (v[0] * v[0] + v[1] * v[1]).sqrt();
+    if len > f32::EPSILON {
+        Some([v[0] / len, v[1] / len])
+    } else {
+        None
+    }
+}
+
+// Accumulated normals of the outer edges meeting at a vertex.
+struct Corner {
+    sum: [f32; 2],
+    first: [f32; 2],
+}
+
+impl Corner {
+    // Returns the miter offset in pixels.
+    fn offset(&self) -> [f32; 2] {
+        let n = normalize(self.sum).unwrap_or(self.first);
+        let cos = n[0] * self.first[0] + n[1] * self.first[1];
+        let scale = FRINGE_WIDTH / cos.max(1.0 / MAX_MITER);
+        [n[0] * scale, n[1] * scale]
+    }
+}
+
+/// Collects a triangle list and computes the fringe around its outer edges.
+///
+/// Memory is reused between triangle lists.
+#[derive(Default)]
+pub(crate) struct Fringe {
+    // Vertices and linear colors of the triangle list.
+    vertices: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    // Snapped positions of the vertices.
+    keys: Vec<VertexKey>,
+    // Counts of triangles sharing an edge.
+    edges: HashMap<(VertexKey, VertexKey), u32>,
+    corners: HashMap<VertexKey, Corner>,
+    // Outer edges as pairs of vertex indices.
+    outer: Vec<(usize, usize)>,
+    out_vertices: Vec<[f32; 2]>,
+    out_colors: Vec<[f32; 4]>,
+}
+
+impl Fringe {
+    /// Starts a new triangle list.
+    pub(crate) fn clear(&mut self) {
+        self.vertices.clear();
+        self.colors.clear();
+    }
+
+    /// Adds triangles to the triangle list.
+    ///
+    /// Colors are in linear space, one per vertex.
+    pub(crate) fn extend(&mut self, vertices: &[[f32; 2]], colors: &[[f32; 4]]) {
+        let n = vertices.len() / 3 * 3;
+        self.vertices.extend_from_slice(&vertices[..n]);
+        self.colors.extend_from_slice(&colors[..n]);
+    }
+
+    /// Returns fringe triangles for the outer edges of the triangle list.
+    ///
+    /// Vertices are in normalized device coordinates, `half_size` is half the
+    /// size of the render target in pixels.
+    pub(crate) fn fringe(&mut self, half_size: [f32; 2]) -> (&[[f32; 2]], &[[f32; 4]]) {
+        let Fringe {
+            ref vertices,
+            ref colors,
+            ref mut keys,
+            ref mut edges,
+            ref mut corners,
+            ref mut outer,
+            ref mut out_vertices,
+            ref mut out_colors,
+        } = *self;
+        let to_px = |v: [f32; 2]| [v[0] * half_size[0], v[1] * half_size[1]];
+
+        keys.clear();
+        keys.extend(vertices.iter().map(|&v| {
+            let p = to_px(v);
+            [(p[0] * SNAP).round() as i32, (p[1] * SNAP).round() as i32]
+        }));
+        edges.clear();
+        for tri in 0..vertices.len() / 3 {
+            for e in 0..3 {
+                let a = keys[tri * 3 + e];
+                let b = keys[tri * 3 + (e + 1) % 3];
+                *edges.entry(edge_key(a, b)).or_insert(0) += 1;
+            }
+        }
+
+        outer.clear();
+        corners.clear();
+        for tri in 0..vertices.len() / 3 {
+            for e in 0..3 {
+                let ia = tri * 3 + e;
+                let ib = tri * 3 + (e + 1) % 3;
+                let ic = tri * 3 + (e + 2) % 3;
+                if edges[&edge_key(keys[ia], keys[ib])] != 1 { continue; }
+
+                let (pa, pb, pc) = (to_px(vertices[ia]), to_px(vertices[ib]), to_px(vertices[ic]));
+                let normal = match normalize([pa[1] - pb[1], pb[0] - pa[0]]) {
+                    None => continue,
+                    Some(normal) => normal,
+                };
+                // Point away from the opposite vertex.
+                let side = normal[0] * (pc[0] - pa[0]) + normal[1] * (pc[1] - pa[1]);
+                if side.abs() <= f32::EPSILON { continue; }
+                let normal = if side > 0.0 { [-normal[0], -normal[1]] } else { normal };
+
+                for &i in &[ia, ib] {
+                    let corner = corners.entry(keys[i]).or_insert(Corner {
+                        sum: [0.0; 2],
+                        first: normal,
+                    });
+                    corner.sum[0] += normal[0];
+                    corner.sum[1] += normal[1];
+                }
+                outer.push((ia, ib));
+            }
+        }
+
+        let outside = |i: usize| {
+            let v = vertices[i];
+            let offset = corners[&keys[i]].offset();
+            [v[0] + offset[0] / half_size[0], v[1] + offset[1] / half_size[1]]
+        };
+        let transparent = |c: [f32; 4]| [c[0], c[1], c[2], 0.0];
+        out_vertices.clear();
+        out_colors.clear();
+        for &(ia, ib) in outer.iter() {
+            let (a, b) = (vertices[ia], vertices[ib]);
+            let (ca, cb) = (colors[ia], colors[ib]);
+            let (oa, ob) = (outside(ia), outside(ib));
+            out_vertices.extend_from_slice(&[a, b, ob, a, ob, oa]);
+            out_colors.extend_from_slice(&[
+                ca, cb, transparent(cb),
+                ca, transparent(cb), transparent(ca)
+            ]);
+        }
+        (out_vertices, out_colors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_edges_across_chunks_have_no_fringe() {
+        let color = [[1.0; 4]; 3];
+        let mut fringe = Fringe::default();
+        // A square split into two triangles, added as separate chunks.
+        // The diagonal of the second triangle is off by rounding error.
+        fringe.extend(&[[0.0, 0.0], [0.5, 0.0], [0.5, 0.5]], &color);
+        fringe.extend(&[[0.0, 0.0], [0.5, 0.500_000_06], [0.0, 0.5]], &color);
+        let (vertices, colors) = fringe.fringe([100.0, 100.0]);
+        // Two fringe triangles for each of the four outer edges.
+        assert_eq!(vertices.len(), 4 * 6);
+        assert_eq!(colors.len(), 4 * 6);
+    }
+
+    #[test]
+    fn clear_starts_a_new_list() {
+        let color = [[1.0; 4]; 3];
+        let mut fringe = Fringe::default();
+        fringe.extend(&[[0.0, 0.0], [0.5, 0.0], [0.5, 0.5]], &color);
+        assert_eq!(fringe.fringe([100.0, 100.0]).0.len(), 3 * 6);
+        fringe.clear();
+        assert_eq!(fringe.fringe([100.0, 100.0]).0.len(), 0);
+    }
+}
@@ -0,0 +1,7 @@
+//! Shaders used by gfx_graphics in addition to the shared 2D shaders.
+
+pub(crate) mod array;
+pub(crate) mod channels;
+pub(crate) mod channels_color;
+pub(crate) mod resolve;
+pub(crate) mod sdf;
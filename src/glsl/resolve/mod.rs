@@ -0,0 +1,7 @@
+//! Shaders for resolving multisampled render targets.
+
+/// Vertex shader for GLSL 1.50
+pub(crate) const VERTEX_GLSL_150_CORE: &[u8] = include_bytes!("150_core.glslv");
+
+/// Fragment shader for GLSL 1.50
+pub(crate) const FRAGMENT_GLSL_150_CORE: &[u8] = include_bytes!("150_core.glslf");
@@ -0,0 +1,11 @@
+//! Shaders for signed distance field rendering.
+
+/// Vertex shader for GLSL 1.20
+pub(crate) const VERTEX_GLSL_120: &[u8] = include_bytes!("120.glslv");
+/// Vertex shader for GLSL 1.50
+pub(crate) const VERTEX_GLSL_150_CORE: &[u8] = include_bytes!("150_core.glslv");
+
+/// Fragment shader for GLSL 1.20
+pub(crate) const FRAGMENT_GLSL_120: &[u8] = include_bytes!("120.glslf");
+/// Fragment shader for GLSL 1.50
+pub(crate) const FRAGMENT_GLSL_150_CORE: &[u8] = include_bytes!("150_core.glslf");
@@ -0,0 +1,356 @@
+//! Glyph caching using atlas textures.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use gfx;
+use graphics::character::{Character, CharacterCache};
+use graphics::types::{FontSize, Scalar};
+use rusttype as rt;
+
+use atlas::AtlasPages;
+use back_end::GfxGraphics;
+use {Error, Texture, TextureContext, TextureSettings};
+
+/// The size of atlas textures storing glyphs.
+pub const GLYPH_ATLAS_SIZE: [u32; 2] = [1024; 2];
+
+// Layout of a rendered glyph.
+#[derive(Clone, Copy)]
+pub(crate) struct Data {
+    pub(crate) offset: [Scalar; 2],
+    pub(crate) advance_size: [Scalar; 2],
+    pub(crate) atlas_offset: [Scalar; 2],
+    pub(crate) atlas_size: [Scalar; 2],
+    pub(crate) atlas: usize,
+    pub(crate) is_invalid: bool,
+}
+
+// Used to check whether a glyph has an outline.
+struct EmptyOutlineBuilder;
+
+impl rt::OutlineBuilder for EmptyOutlineBuilder {
+    fn move_to(&mut self, _x: f32, _y: f32) {}
+    fn line_to(&mut self, _x: f32, _y: f32) {}
+    fn quad_to(&mut self, _x1: f32, _y1: f32, _x: f32, _y: f32) {}
+    fn curve_to(&mut self, _x1: f32, _y1: f32, _x2: f32, _y2: f32, _x: f32, _y: f32) {}
+    fn close(&mut self) {}
+}
+
+/// Identifies a font loaded into an `AtlasGlyphCache`.
+///
+/// The default value refers to the font the cache was created with.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+/// Stores rendered glyphs for text rendering.
+///
+/// Glyphs of all sizes are packed into a few large atlas textures,
+/// which are updated through the encoder of `GfxGraphics`.
+/// Since `GfxGraphics` batches triangles sharing a texture,
+/// text using glyphs from one atlas is rendered in a single draw call.
+///
+/// `TextLayout` and `RichText` write new glyphs before drawing them.
+/// When drawing through `CharacterCache`, e.g. with `graphics::Text`,
+/// call `flush_uploads` after drawing the text.
+///
+/// Additional fonts can be added, sharing the same atlas textures.
+/// Characters missing from a font are looked up in the fallback fonts,
+/// in the order they were added.
+///
+/// Unlike `GlyphCache`, which creates one texture per glyph,
+/// no encoder needs to be flushed before drawing.
+pub struct AtlasGlyphCache<'a, F, R, C>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    /// The font.
+    pub font: rt::Font<'a>,
+    /// The context used to create atlas textures.
+    ///
+    /// New glyphs are written with `flush_uploads`,
+    /// so the encoder of the context does not need to be flushed.
+    pub factory: TextureContext<F, R, C>,
+    // Fonts added after the first one.
+    fonts: Vec<rt::Font<'a>>,
+    fallbacks: Vec<FontId>,
+    // Maps from requested font and character to the font containing it.
+    resolved: HashMap<(FontId, char), FontId>,
+    atlases: AtlasPages<R>,
+    // Maps from containing font, font size in pixels and character to glyph layout.
+    data: HashMap<(FontId, u32, char), Data>,
+}
+
+impl<'a, F, R, C> AtlasGlyphCache<'a, F, R, C>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    /// Creates a new glyph cache from a font.
+    pub fn from_font(
+        font: rt::Font<'a>,
+        factory: TextureContext<F, R, C>,
+        settings: TextureSettings
+    ) -> Self {
+        AtlasGlyphCache {
+            font,
+            factory,
+            fonts: vec![],
+            fallbacks: vec![],
+            resolved: HashMap::new(),
+            atlases: AtlasPages::new(GLYPH_ATLAS_SIZE, settings),
+            data: HashMap::new(),
+        }
+    }
+
+    /// Creates a new glyph cache from an ordered list of fonts.
+    ///
+    /// The first font is the default font, the rest are fallbacks.
+    /// Panics if the list is empty.
+    pub fn from_fonts(
+        fonts: Vec<rt::Font<'a>>,
+        factory: TextureContext<F, R, C>,
+        settings: TextureSettings
+    ) -> Self {
+        let mut fonts = fonts.into_iter();
+        let font = fonts.next().expect("Expected at least one font");
+        let mut cache = AtlasGlyphCache::from_font(font, factory, settings);
+        for font in fonts {
+            let id = cache.add_font(font);
+            cache.add_fallback(id);
+        }
+        cache
+    }
+
+    /// Creates a new glyph cache loading a font from file.
+    pub fn new<P>(
+        font: P,
+        factory: TextureContext<F, R, C>,
+        settings: TextureSettings
+    ) -> io::Result<AtlasGlyphCache<'static, F, R, C>>
+        where P: AsRef<Path>
+    {
+        Ok(AtlasGlyphCache::from_font(load_font(font)?, factory, settings))
+    }
+
+    /// Adds a font and returns its id.
+    pub fn add_font(&mut self, font: rt::Font<'a>) -> FontId {
+        self.fonts.push(font);
+        FontId(self.fonts.len())
+    }
+
+    /// Adds a font loaded from file and returns its id.
+    pub fn add_font_file<P>(&mut self, font: P) -> io::Result<FontId>
+        where P: AsRef<Path>
+    {
+        Ok(self.add_font(load_font(font)?))
+    }
+
+    /// Appends a font to the fallback chain.
+    ///
+    /// Characters missing from a font are rendered with the first
+    /// fallback containing them.
+    pub fn add_fallback(&mut self, font: FontId) {
+        if !self.fallbacks.contains(&font) {
+            self.fallbacks.push(font);
+            self.resolved.clear();
+        }
+    }
+
+    /// Returns the fallback chain.
+    pub fn fallbacks(&self) -> &[FontId] {
+        &self.fallbacks
+    }
+
+    /// Returns the font used to render a character requested from a font.
+    ///
+    /// This is the font itself, or the first fallback containing the character.
+    /// When no font contains the character, the requested font is returned.
+    pub fn resolve(&mut self, font: FontId, ch: char) -> FontId {
+        if let Some(&resolved) = self.resolved.get(&(font, ch)) {
+            return resolved;
+        }
+        let has_glyph = |f: &rt::Font| f.glyph(ch).id() != rt::GlyphId(0);
+        let resolved = if has_glyph(self.font(font)) {
+            font
+        } else {
+            self.fallbacks.iter().cloned()
+                .find(|&id| has_glyph(self.font(id)))
+                .unwrap_or(font)
+        };
+        self.resolved.insert((font, ch), resolved);
+        resolved
+    }
+
+    /// Returns a font.
+    ///
+    /// Panics if the font does not belong to this cache.
+    pub fn font(&self, id: FontId) -> &rt::Font<'a> {
+        if id.0 == 0 { &self.font } else { &self.fonts[id.0 - 1] }
+    }
+
+    /// Returns `ch` using a font for `size`, loading it if needed.
+    pub fn font_character(
+        &mut self,
+        font: FontId,
+        size: FontSize,
+        ch: char
+    ) -> Result<Character<'_, Texture<R>>, Error> {
+        let data = self.glyph(font, size, ch)?;
+        Ok(self.to_character(data))
+    }
+
+    /// Loads all characters in the `chars` iterator for `size`.
+    pub fn preload_chars<I>(&mut self, size: FontSize, chars: I) -> Result<(), Error>
+        where I: Iterator<Item = char>
+    {
+        for ch in chars {
+            self.character(size, ch)?;
+        }
+        Ok(())
+    }
+
+    /// Loads all the printable ASCII characters for `size`. Includes space.
+    pub fn preload_printable_ascii(&mut self, size: FontSize) -> Result<(), Error> {
+        // [0x20, 0x7F) contains all printable ASCII characters ([' ', '~'])
+        self.preload_chars(size, (0x20u8..0x7F).map(|ch| ch as char))
+    }
+
+    /// Returns `ch` for `size` if it is already cached. Does not load.
+    pub fn opt_character(&self, size: FontSize, ch: char) -> Option<Character<'_, Texture<R>>> {
+        let font = self.resolved.get(&(FontId(0), ch))?;
+        self.data.get(&(*font, pixel_size(size), ch)).map(|&data| self.to_character(data))
+    }
+
+    /// Returns the atlas textures.
+    pub fn atlases(&self) -> impl Iterator<Item = &Texture<R>> {
+        self.atlases.textures()
+    }
+
+    /// Writes new glyphs through the encoder of `GfxGraphics`.
+    ///
+    /// Glyphs are written before the triangles batched in `GfxGraphics`,
+    /// so call this after drawing text through `CharacterCache`
+    /// in the same `Gfx2d::draw`.
+    /// Batches written earlier, e.g. when text uses several atlas textures,
+    /// show their new glyphs from the next frame on.
+    pub fn flush_uploads<C2>(&mut self, g: &mut GfxGraphics<R, C2>)
+        where C2: gfx::CommandBuffer<R>
+    {
+        self.atlases.flush_uploads(g.encoder);
+    }
+
+    // Returns the atlas texture with an index.
+    pub(crate) fn atlas(&self, atlas: usize) -> &Texture<R> {
+        self.atlases.texture(atlas)
+    }
+
+    // Returns the layout of a glyph, rendering it if needed.
+    //
+    // Glyphs are stored once per containing font, regardless of the
+    // requested font they were resolved from.
+    pub(crate) fn glyph(&mut self, font: FontId, size: FontSize, ch: char) -> Result<Data, Error> {
+        let font = self.resolve(font, ch);
+        let size = pixel_size(size);
+        match self.data.get(&(font, size, ch)) {
+            Some(&data) => Ok(data),
+            None => {
+                let data = self.render(font, size, ch)?;
+                self.data.insert((font, size, ch), data);
+                Ok(data)
+            }
+        }
+    }
+
+    fn to_character(&self, data: Data) -> Character<'_, Texture<R>> {
+        Character {
+            offset: data.offset,
+            advance_size: data.advance_size,
+            atlas_offset: data.atlas_offset,
+            atlas_size: data.atlas_size,
+            texture: self.atlases.texture(data.atlas),
+            is_invalid: data.is_invalid,
+        }
+    }
+
+    // Renders a glyph into an atlas.
+    fn render(&mut self, font: FontId, size: u32, ch: char) -> Result<Data, Error> {
+        let scale = rt::Scale::uniform(size as f32);
+        let font = self.font(font);
+        let mut glyph = font.glyph(ch).scaled(scale);
+
+        // Some fonts do not contain glyph zero as fallback, instead try U+FFFD.
+        if glyph.id() == rt::GlyphId(0) && !glyph.build_outline(&mut EmptyOutlineBuilder) {
+            glyph = font.glyph('\u{FFFD}').scaled(scale);
+        }
+
+        let h_metrics = glyph.h_metrics();
+        let bounding_box = glyph.exact_bounding_box().unwrap_or(rt::Rect {
+            min: rt::Point { x: 0.0, y: 0.0 },
+            max: rt::Point { x: 0.0, y: 0.0 },
+        });
+        let is_invalid = glyph.id() == rt::GlyphId(0);
+        let glyph = glyph.positioned(rt::point(0.0, 0.0));
+        let pixel_bounding_box = glyph.pixel_bounding_box().unwrap_or(rt::Rect {
+            min: rt::Point { x: 0, y: 0 },
+            max: rt::Point { x: 0, y: 0 },
+        });
+        // Leave one pixel of space around glyphs to avoid bleeding.
+        let tile = [
+            (pixel_bounding_box.width() + 2) as u32,
+            (pixel_bounding_box.height() + 2) as u32,
+        ];
+
+        let mut alpha = vec![0; (tile[0] * tile[1]) as usize];
+        glyph.draw(|x, y, v| {
+            let pos = (x + 1) + (y + 1) * tile[0];
+            alpha[pos as usize] = (255.0 * v) as u8;
+        });
+        let memory = ::ops::alpha_to_rgba8(&alpha, tile);
+
+        let (atlas, offset) = self.atlases.add(&mut self.factory, tile, &memory)?;
+
+        Ok(Data {
+            offset: [
+                bounding_box.min.x as Scalar - 1.0,
+                -pixel_bounding_box.min.y as Scalar + 1.0,
+            ],
+            advance_size: [h_metrics.advance_width as Scalar, 0.0],
+            atlas_offset: [offset[0] as Scalar, offset[1] as Scalar],
+            atlas_size: [tile[0] as Scalar, tile[1] as Scalar],
+            atlas,
+            is_invalid,
+        })
+    }
+}
+
+// Loads a font from file.
+fn load_font<P>(path: P) -> io::Result<rt::Font<'static>>
+    where P: AsRef<Path>
+{
+    let mut file_buffer = Vec::new();
+    File::open(path)?.read_to_end(&mut file_buffer)?;
+    rt::Font::try_from_vec(file_buffer).ok_or_else(||
+        io::Error::new(io::ErrorKind::InvalidData, "invalid font"))
+}
+
+// Converts points to pixels.
+pub(crate) fn pixel_size(size: FontSize) -> u32 {
+    ((size as f32) * 1.333).round() as u32
+}
+
+impl<'a, F, R, C> CharacterCache for AtlasGlyphCache<'a, F, R, C>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    type Texture = Texture<R>;
+    type Error = Error;
+
+    fn character(&mut self, size: FontSize, ch: char) -> Result<Character<'_, Texture<R>>, Error> {
+        self.font_character(FontId(0), size, ch)
+    }
+}
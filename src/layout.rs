@@ -0,0 +1,626 @@
+//! Text layout with wrapping, alignment and kerning.
+//!
+//! Text is measured against an `AtlasGlyphCache`, broken into lines at spaces
+//! and newlines, and positioned relative to the upper left corner.
+//! The resulting glyph quads are rendered through `GfxGraphics`,
+//! with one draw call per atlas texture.
+//!
+//! The same engine lays out plain text and rich text mixing fonts and sizes.
+
+use gfx;
+use graphics::{DrawState, Graphics};
+use graphics::math::Matrix2d;
+use graphics::types::{Color, FontSize};
+use graphics::triangulation::{rect_tri_list_uv, rect_tri_list_xy};
+use graphics::BACK_END_MAX_VERTEX_COUNT as BUFFER_SIZE;
+use rusttype as rt;
+
+use back_end::GfxGraphics;
+use glyph::{pixel_size, FontId, AtlasGlyphCache};
+use Error;
+
+/// Horizontal alignment of lines.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    /// Aligns lines to the left edge.
+    Left,
+    /// Centers lines.
+    Center,
+    /// Aligns lines to the right edge.
+    Right,
+    /// Stretches spaces between words to fill the width.
+    ///
+    /// The last line of a paragraph is aligned to the left.
+    Justified,
+}
+
+
+/// A character positioned by a layout.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PositionedChar {
+    /// The character.
+    pub ch: char,
+    /// The index of the span the character belongs to.
+    ///
+    /// This is always zero for plain text.
+    pub span: usize,
+    /// The pen position on the baseline.
+    pub pos: [f64; 2],
+}
+
+/// Text positioned by a layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextBlock {
+    /// The positioned characters, excluding spaces and newlines.
+    pub chars: Vec<PositionedChar>,
+    /// The number of lines.
+    pub lines: usize,
+    /// The width and height of the text.
+    pub size: [f64; 2],
+}
+
+/// Lays out and renders text using a glyph cache.
+#[derive(Copy, Clone, Debug)]
+pub struct TextLayout {
+    /// The color.
+    pub color: Color,
+    /// The font size.
+    pub font_size: FontSize,
+    /// The font.
+    pub font: FontId,
+    /// The width to wrap lines at, if any.
+    pub max_width: Option<f64>,
+    /// The alignment of lines.
+    pub align: TextAlign,
+    /// The distance between baselines relative to the font line height.
+    pub line_spacing: f64,
+}
+
+// Font and size of characters.
+#[derive(Copy, Clone)]
+pub(crate) struct Style {
+    pub(crate) font: FontId,
+    pub(crate) font_size: FontSize,
+}
+
+// A character with the index of its style.
+#[derive(Copy, Clone)]
+struct Item {
+    ch: char,
+    style: usize,
+}
+
+// A line as a range of items.
+struct Line {
+    start: usize,
+    end: usize,
+    width: f64,
+    // Whether this line ends a paragraph.
+    last: bool,
+}
+
+// Vertices and texture coordinates of quads sharing an atlas.
+pub(crate) type Batch = (Vec<[f32; 2]>, Vec<[f32; 2]>);
+
+// Measures characters for layout.
+trait Metrics {
+    // Returns the advance width of a character.
+    fn advance(&mut self, item: Item) -> Result<f64, Error>;
+
+    // Returns the kerning between two adjacent characters.
+    fn kern(&mut self, prev: Item, item: Item) -> f64;
+
+    // Returns the vertical metrics of the font a character is rendered with.
+    fn v_metrics(&mut self, item: Item) -> rt::VMetrics;
+
+    // Adds the character at `i` to the width of the characters from `start`.
+    fn extend(&mut self, items: &[Item], width: f64, start: usize, i: usize)
+        -> Result<f64, Error>
+    {
+        let kern = if i > start { self.kern(items[i - 1], items[i]) } else { 0.0 };
+        Ok(width + kern + self.advance(items[i])?)
+    }
+}
+
+// Measures characters with kerning.
+struct Measure<'b, 'a: 'b, F: 'b, R: 'b, C: 'b>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    cache: &'b mut AtlasGlyphCache<'a, F, R, C>,
+    styles: &'b [Style],
+}
+
+impl<'b, 'a, F, R, C> Metrics for Measure<'b, 'a, F, R, C>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    fn advance(&mut self, item: Item) -> Result<f64, Error> {
+        let style = self.styles[item.style];
+        Ok(self.cache.glyph(style.font, style.font_size, item.ch)?.advance_size[0])
+    }
+
+    // Kerning only applies between characters rendered with the same font and size.
+    fn kern(&mut self, prev: Item, item: Item) -> f64 {
+        let (a, b) = (self.styles[prev.style], self.styles[item.style]);
+        if a.font_size != b.font_size { return 0.0; }
+        let font = self.cache.resolve(a.font, prev.ch);
+        if font != self.cache.resolve(b.font, item.ch) { return 0.0; }
+        let scale = rt::Scale::uniform(pixel_size(a.font_size) as f32);
+        self.cache.font(font).pair_kerning(scale, prev.ch, item.ch) as f64
+    }
+
+    // Uses the font the character is rendered with.
+    fn v_metrics(&mut self, item: Item) -> rt::VMetrics {
+        let style = self.styles[item.style];
+        let scale = rt::Scale::uniform(pixel_size(style.font_size) as f32);
+        let font = self.cache.resolve(style.font, item.ch);
+        self.cache.font(font).v_metrics(scale)
+    }
+}
+
+// Breaks a paragraph into lines.
+//
+// Spaces at the end of a line hang past it:
+// they are left out of the line and never cause a break.
+fn break_lines<M: Metrics>(
+    items: &[Item],
+    start: usize,
+    end: usize,
+    max_width: Option<f64>,
+    m: &mut M,
+    lines: &mut Vec<Line>
+) -> Result<(), Error> {
+    let fits = |width: f64| match max_width {
+        None => true,
+        Some(max) => width <= max,
+    };
+    // Keep the width of the line as a running total, measuring each character once,
+    // or twice in words broken between characters.
+    let mut line_start = start;
+    // The end and width of the line without trailing spaces.
+    let mut line_end = start;
+    let mut line_width = 0.0;
+    let mut word_start = start;
+    while word_start <= end {
+        let word_end = items[word_start..end].iter()
+            .position(|item| item.ch == ' ')
+            .map_or(end, |i| word_start + i);
+        if word_start == word_end {
+            // Spaces only count once a word follows them on the line.
+            word_start = word_end + 1;
+            continue;
+        }
+        let mut word_width = 0.0;
+        for i in word_start..word_end {
+            word_width = m.extend(items, word_width, word_start, i)?;
+        }
+        // The width of the line up to the word, including the spaces before it.
+        let mut before = line_width;
+        for i in line_end..word_start {
+            before = m.extend(items, before, line_start, i)?;
+        }
+        let mut width = before + word_width;
+        if word_start > line_start {
+            width += m.kern(items[word_start - 1], items[word_start]);
+        }
+        if line_end > line_start && !fits(width) {
+            // Leave out the spaces before the word.
+            lines.push(Line {
+                start: line_start,
+                end: line_end,
+                width: line_width,
+                last: false,
+            });
+            line_start = word_start;
+            before = 0.0;
+            width = word_width;
+        }
+        if !fits(width) {
+            // Break words that do not fit on a line of their own.
+            width = before;
+            for i in word_start..word_end {
+                let w = m.extend(items, width, line_start, i)?;
+                if i > line_start && !fits(w) {
+                    lines.push(Line {
+                        start: line_start,
+                        end: i,
+                        width,
+                        last: false,
+                    });
+                    line_start = i;
+                    width = m.advance(items[i])?;
+                } else {
+                    width = w;
+                }
+            }
+        }
+        line_end = word_end;
+        line_width = width;
+        word_start = word_end + 1;
+    }
+    lines.push(Line {
+        start: line_start,
+        end: line_end,
+        width: line_width,
+        last: true,
+    });
+    Ok(())
+}
+
+// Positions styled characters with the upper left corner at the origin.
+//
+// Each character refers to a style by index.
+pub(crate) fn layout<F, R, C, I>(
+    chars: I,
+    styles: &[Style],
+    max_width: Option<f64>,
+    align: TextAlign,
+    line_spacing: f64,
+    cache: &mut AtlasGlyphCache<F, R, C>
+) -> Result<TextBlock, Error>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>,
+          I: Iterator<Item = (char, usize)>
+{
+    let items: Vec<Item> = chars.map(|(ch, style)| Item { ch, style }).collect();
+    let mut m = Measure {
+        cache,
+        styles,
+    };
+    let default_style = if styles.is_empty() { None } else { Some(0) };
+    layout_items(&items, default_style, max_width, align, line_spacing, &mut m)
+}
+
+// Positions characters measured with some metrics.
+//
+// Empty text uses the metrics of the default style for its line, if any.
+fn layout_items<M: Metrics>(
+    items: &[Item],
+    default_style: Option<usize>,
+    max_width: Option<f64>,
+    align: TextAlign,
+    line_spacing: f64,
+    m: &mut M
+) -> Result<TextBlock, Error> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, item) in items.iter().enumerate() {
+        if item.ch == '\n' {
+            break_lines(items, start, i, max_width, m, &mut lines)?;
+            start = i + 1;
+        }
+    }
+    break_lines(items, start, items.len(), max_width, m, &mut lines)?;
+
+    let widest = lines.iter().fold(0.0, |w: f64, line| w.max(line.width));
+    let width = max_width.unwrap_or(widest);
+    let mut chars = vec![];
+    let mut top = 0.0;
+    let mut bottom = 0.0;
+    for line in &lines {
+        // Use the tallest style on the line. Empty lines use the newline ending them,
+        // or the one before them at the end of the text.
+        let blank;
+        let styled = if line.start < line.end {
+            &items[line.start..line.end]
+        } else if !items.is_empty() {
+            let i = line.end.min(items.len() - 1);
+            &items[i..i + 1]
+        } else if let Some(style) = default_style {
+            blank = [Item { ch: ' ', style }];
+            &blank[..]
+        } else {
+            &[]
+        };
+        let (ascent, descent, line_gap) = styled.iter().fold((0.0, 0.0, 0.0),
+            |(a, d, g): (f64, f64, f64), item| {
+                let v = m.v_metrics(*item);
+                (a.max(v.ascent as f64), d.min(v.descent as f64), g.max(v.line_gap as f64))
+            });
+
+        let spaces = items[line.start..line.end].iter().filter(|item| item.ch == ' ').count();
+        let (x, extra) = match align {
+            TextAlign::Left => (0.0, 0.0),
+            TextAlign::Center => ((width - line.width) / 2.0, 0.0),
+            TextAlign::Right => (width - line.width, 0.0),
+            TextAlign::Justified if line.last || spaces == 0 => (0.0, 0.0),
+            TextAlign::Justified => (0.0, (width - line.width).max(0.0) / spaces as f64),
+        };
+        let mut pos = [x, top + ascent];
+        for i in line.start..line.end {
+            let item = items[i];
+            if i > line.start { pos[0] += m.kern(items[i - 1], item); }
+            if item.ch == ' ' {
+                pos[0] += extra;
+            } else {
+                chars.push(PositionedChar { ch: item.ch, span: item.style, pos });
+            }
+            pos[0] += m.advance(item)?;
+        }
+        bottom = top + ascent - descent;
+        top += (ascent - descent + line_gap) * line_spacing;
+    }
+
+    Ok(TextBlock {
+        chars,
+        lines: lines.len(),
+        size: [width, bottom],
+    })
+}
+
+// Adds the glyph quad of a character to batches per atlas.
+//
+// Returns the atlas index.
+pub(crate) fn push_glyph<F, R, C>(
+    c: &PositionedChar,
+    style: Style,
+    transform: Matrix2d,
+    cache: &mut AtlasGlyphCache<F, R, C>,
+    batches: &mut Vec<Batch>
+) -> Result<usize, Error>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    let glyph = cache.glyph(style.font, style.font_size, c.ch)?;
+    if batches.len() <= glyph.atlas {
+        batches.resize(glyph.atlas + 1, (vec![], vec![]));
+    }
+    let batch = &mut batches[glyph.atlas];
+    batch.0.extend_from_slice(&rect_tri_list_xy(transform, [
+        c.pos[0] + glyph.offset[0],
+        c.pos[1] - glyph.offset[1],
+        glyph.atlas_size[0],
+        glyph.atlas_size[1],
+    ]));
+    batch.1.extend_from_slice(&rect_tri_list_uv(cache.atlas(glyph.atlas), [
+        glyph.atlas_offset[0],
+        glyph.atlas_offset[1],
+        glyph.atlas_size[0],
+        glyph.atlas_size[1],
+    ]));
+    Ok(glyph.atlas)
+}
+
+impl TextLayout {
+    /// Creates a new left aligned layout with black color and no wrapping.
+    pub fn new(font_size: FontSize) -> TextLayout {
+        TextLayout {
+            color: [0.0, 0.0, 0.0, 1.0],
+            font_size,
+            font: FontId::default(),
+            max_width: None,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+        }
+    }
+
+    /// Sets color.
+    pub fn color(mut self, value: Color) -> Self {
+        self.color = value;
+        self
+    }
+
+    /// Sets font size.
+    pub fn font_size(mut self, value: FontSize) -> Self {
+        self.font_size = value;
+        self
+    }
+
+    /// Sets font.
+    pub fn font(mut self, value: FontId) -> Self {
+        self.font = value;
+        self
+    }
+
+    /// Sets the width to wrap lines at.
+    pub fn max_width(mut self, value: f64) -> Self {
+        self.max_width = Some(value);
+        self
+    }
+
+    /// Sets alignment.
+    pub fn align(mut self, value: TextAlign) -> Self {
+        self.align = value;
+        self
+    }
+
+    /// Sets line spacing.
+    pub fn line_spacing(mut self, value: f64) -> Self {
+        self.line_spacing = value;
+        self
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            font: self.font,
+            font_size: self.font_size,
+        }
+    }
+
+    /// Returns the width and height of text.
+    pub fn measure<F, R, C>(
+        &self,
+        text: &str,
+        cache: &mut AtlasGlyphCache<F, R, C>
+    ) -> Result<[f64; 2], Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>
+    {
+        Ok(self.layout(text, cache)?.size)
+    }
+
+    /// Positions text with the upper left corner at the origin.
+    ///
+    /// Lines are broken at newlines, and at spaces when exceeding `max_width`.
+    /// Words wider than `max_width` are broken between characters.
+    pub fn layout<F, R, C>(
+        &self,
+        text: &str,
+        cache: &mut AtlasGlyphCache<F, R, C>
+    ) -> Result<TextBlock, Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>
+    {
+        layout(text.chars().map(|ch| (ch, 0)), &[self.style()],
+               self.max_width, self.align, self.line_spacing, cache)
+    }
+
+    /// Draws text with the upper left corner at the origin.
+    ///
+    /// Glyphs sharing an atlas texture are rendered in one call.
+    pub fn draw<F, R, C, C2>(
+        &self,
+        text: &str,
+        cache: &mut AtlasGlyphCache<F, R, C>,
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        g: &mut GfxGraphics<R, C2>
+    ) -> Result<(), Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>,
+              C2: gfx::CommandBuffer<R>
+    {
+        let block = self.layout(text, cache)?;
+        self.draw_block(&block, cache, draw_state, transform, g)
+    }
+
+    /// Draws text positioned by `layout`.
+    pub fn draw_block<F, R, C, C2>(
+        &self,
+        block: &TextBlock,
+        cache: &mut AtlasGlyphCache<F, R, C>,
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        g: &mut GfxGraphics<R, C2>
+    ) -> Result<(), Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>,
+              C2: gfx::CommandBuffer<R>
+    {
+        let mut batches: Vec<Batch> = vec![];
+        for c in &block.chars {
+            push_glyph(c, self.style(), transform, cache, &mut batches)?;
+        }
+        cache.flush_uploads(g);
+
+        // Keep chunks a multiple of whole quads.
+        let chunk = BUFFER_SIZE / 6 * 6;
+        for (i, (vertices, uvs)) in batches.iter().enumerate() {
+            if vertices.is_empty() { continue; }
+            g.tri_list_uv(draw_state, &self.color, cache.atlas(i), |f| {
+                for (v, uv) in vertices.chunks(chunk).zip(uvs.chunks(chunk)) {
+                    f(v, uv);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every character is 10 wide without kerning.
+    // Style 1 is twice as tall as style 0.
+    struct Mono;
+
+    impl Metrics for Mono {
+        fn advance(&mut self, _: Item) -> Result<f64, Error> {
+            Ok(10.0)
+        }
+
+        fn kern(&mut self, _: Item, _: Item) -> f64 {
+            0.0
+        }
+
+        fn v_metrics(&mut self, item: Item) -> rt::VMetrics {
+            let scale = (item.style + 1) as f32;
+            rt::VMetrics { ascent: 8.0 * scale, descent: -2.0 * scale, line_gap: 0.0 }
+        }
+    }
+
+    fn layout_text(text: &str, max_width: Option<f64>, align: TextAlign) -> TextBlock {
+        let items: Vec<Item> = text.chars().map(|ch| Item { ch, style: 0 }).collect();
+        layout_items(&items, Some(0), max_width, align, 1.0, &mut Mono).unwrap()
+    }
+
+    fn positions(block: &TextBlock) -> Vec<(char, [f64; 2])> {
+        block.chars.iter().map(|c| (c.ch, c.pos)).collect()
+    }
+
+    #[test]
+    fn lines_wrap_at_spaces() {
+        let block = layout_text("aa bb cc", Some(50.0), TextAlign::Left);
+        assert_eq!(block.lines, 2);
+        assert_eq!(positions(&block), vec![
+            ('a', [0.0, 8.0]), ('a', [10.0, 8.0]),
+            ('b', [30.0, 8.0]), ('b', [40.0, 8.0]),
+            ('c', [0.0, 18.0]), ('c', [10.0, 18.0]),
+        ]);
+        assert_eq!(block.size, [50.0, 20.0]);
+    }
+
+    #[test]
+    fn long_words_break_between_characters() {
+        let block = layout_text("aaaa", Some(25.0), TextAlign::Left);
+        assert_eq!(block.lines, 2);
+        assert_eq!(block.chars[2].pos, [0.0, 18.0]);
+    }
+
+    #[test]
+    fn newlines_start_lines_with_height() {
+        let block = layout_text("\na\n\nb\n", None, TextAlign::Left);
+        assert_eq!(block.lines, 5);
+        assert_eq!(positions(&block), vec![('a', [0.0, 18.0]), ('b', [0.0, 38.0])]);
+        assert_eq!(block.size, [10.0, 50.0]);
+
+        let block = layout_text("", None, TextAlign::Left);
+        assert_eq!(block.lines, 1);
+        assert_eq!(block.size, [0.0, 10.0]);
+    }
+
+    #[test]
+    fn blank_lines_use_the_style_of_their_newline() {
+        let items = [Item { ch: '\n', style: 1 }, Item { ch: 'a', style: 0 }];
+        let block = layout_items(&items, Some(0), None, TextAlign::Left, 1.0, &mut Mono)
+            .unwrap();
+        assert_eq!(block.chars[0].pos, [0.0, 28.0]);
+    }
+
+    #[test]
+    fn trailing_spaces_hang() {
+        let block = layout_text("aa ", Some(20.0), TextAlign::Left);
+        assert_eq!(block.lines, 1);
+        let block = layout_text("aa bb   ", Some(50.0), TextAlign::Left);
+        assert_eq!(block.lines, 1);
+        assert_eq!(layout_text("aa  ", None, TextAlign::Left).size, [20.0, 10.0]);
+    }
+
+    #[test]
+    fn lines_are_aligned_without_trailing_spaces() {
+        let first_x = |align| layout_text("aa  ", Some(50.0), align).chars[0].pos[0];
+        assert_eq!(first_x(TextAlign::Left), 0.0);
+        assert_eq!(first_x(TextAlign::Center), 15.0);
+        assert_eq!(first_x(TextAlign::Right), 30.0);
+    }
+
+    #[test]
+    fn justified_lines_stretch_spaces_except_the_last() {
+        let block = layout_text("aa b cc d", Some(50.0), TextAlign::Justified);
+        assert_eq!(block.lines, 2);
+        assert_eq!(positions(&block), vec![
+            ('a', [0.0, 8.0]), ('a', [10.0, 8.0]), ('b', [40.0, 8.0]),
+            ('c', [0.0, 18.0]), ('c', [10.0, 18.0]), ('d', [30.0, 18.0]),
+        ]);
+    }
+}
@@ -53,18 +53,60 @@
 extern crate gfx;
 extern crate draw_state;
 extern crate gfx_texture;
+extern crate image;
+#[macro_use]
+extern crate log;
 extern crate graphics;
+extern crate rusttype;
 extern crate shaders_graphics2d as shaders;
 extern crate shader_version;
 
 pub use gfx_texture::*;
 
 pub use back_end::{ Gfx2d, GfxGraphics };
-// pub use glyph::Error as GlyphError;
-// pub use glyph::GlyphCache;
+pub use render_target::RenderTarget;
+pub use channels::{ChannelLayout, ChannelTexture};
+pub use debug::DebugOverlay;
+pub use compressed::{CompressedFormat, CompressedImage, CompressedTexture};
+pub use dynamic_texture::DynamicTexture;
+pub use sdf::{SdfGlyphCache, SdfText, SDF_ATLAS_SIZE, SDF_BASE_SIZE, SDF_SPREAD};
+pub use glyph::{AtlasGlyphCache, FontId, GLYPH_ATLAS_SIZE};
+pub use layout::{PositionedChar, TextAlign, TextBlock, TextLayout};
+pub use mipmap::{MipmapGeneration, MipmapTexture};
+pub use nine_slice::NineSlice;
+pub use rich_text::{RichText, Span};
+pub use stats::{DrawCalls, FrameStats, GpuTimes, GpuTimesError};
+pub use texture_array::TextureArray;
+pub use texture_atlas::{AtlasImage, TextureAtlas, TEXTURE_ATLAS_SIZE};
+pub use trace::{Trace, TraceCall, TraceChunk};
+pub use texture_region::{RegionGraphics, TextureRegion};
 
 /// Stores textures for text rendering.
 pub type GlyphCache<'a, F, R, C> =
     graphics::glyph_cache::rusttype::GlyphCache<'a, TextureContext<F, R, C>, Texture<R>>;
 
+mod astc;
+mod atlas;
 mod back_end;
+mod block;
+mod channels;
+mod compressed;
+mod debug;
+mod dynamic_texture;
+mod edge_aa;
+mod glyph;
+mod glsl;
+mod layout;
+mod mipmap;
+mod nine_slice;
+mod packer;
+mod render_target;
+mod rich_text;
+mod sampler;
+mod sdf;
+mod stats;
+mod texture_array;
+mod texture_atlas;
+mod texture_region;
+mod trace;
+mod upload;
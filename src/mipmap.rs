@@ -0,0 +1,175 @@
+//! Textures with generated mip chains.
+
+use std::path::Path;
+
+use gfx;
+use gfx::format::{ChannelType, Srgba8, SurfaceTyped, R8_G8_B8_A8};
+use gfx::memory::{Bind, Typed, Usage};
+use gfx::texture::{AaMode, Info, Kind, Mipmap};
+use image::{self, imageops, RgbaImage};
+
+use compressed::texture_size;
+use sampler::mipmap_sampler_info;
+use {Error, Flip, Texture, TextureContext, TextureSettings};
+
+/// Where the mip chain of a texture is generated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipmapGeneration {
+    /// Generate levels on the GPU with the encoder of the texture context.
+    ///
+    /// Flush the encoder of the texture context before drawing the texture,
+    /// like after `Texture::update`.
+    Gpu,
+    /// Downscale levels on the CPU and upload them with the texture.
+    ///
+    /// Slower to create, but does not depend on driver support.
+    Cpu,
+}
+
+/// Creates textures with a full mip chain.
+///
+/// Mipmaps are generated when `TextureSettings::get_generate_mipmap` is set,
+/// e.g. with `TextureSettings::new().mipmap(Filter::Linear)`.
+/// Otherwise, textures are created like `Texture::from_image`.
+///
+/// The minify and mipmap filters of the settings select the sampler:
+/// if either is linear, sampling is trilinear, otherwise nearest.
+/// gfx has one filter setting for minifying and magnifying,
+/// so the magnify filter follows the same choice.
+///
+/// `Texture::update` only writes the first level,
+/// so create a new texture to refresh the mip chain.
+pub trait MipmapTexture<R: gfx::Resources>: Sized {
+    /// Creates a texture from image.
+    fn from_image_mipmapped<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        img: &RgbaImage,
+        settings: &TextureSettings,
+        generation: MipmapGeneration
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>;
+
+    /// Creates a texture from path.
+    fn from_path_mipmapped<F, C, P>(
+        context: &mut TextureContext<F, R, C>,
+        path: P,
+        flip: Flip,
+        settings: &TextureSettings,
+        generation: MipmapGeneration
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>,
+              P: AsRef<Path>
+    {
+        let img = image::open(path)?.to_rgba8();
+        let img = match flip {
+            Flip::Vertical => imageops::flip_vertical(&img),
+            Flip::Horizontal => imageops::flip_horizontal(&img),
+            Flip::Both => imageops::flip_horizontal(&imageops::flip_vertical(&img)),
+            Flip::None => img,
+        };
+        Self::from_image_mipmapped(context, &img, settings, generation)
+    }
+}
+
+impl<R: gfx::Resources> MipmapTexture<R> for Texture<R> {
+    fn from_image_mipmapped<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        img: &RgbaImage,
+        settings: &TextureSettings,
+        generation: MipmapGeneration
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        let (width, height) = img.dimensions();
+        if !settings.get_generate_mipmap() || width == 0 || height == 0 {
+            return Texture::from_image(context, img, settings);
+        }
+        let (w, h) = texture_size([width, height])?;
+
+        let levels = mip_levels(width, height);
+        let cpu_levels;
+        let (data, mipmap, bind) = match generation {
+            MipmapGeneration::Gpu => {
+                (vec![img.as_raw().as_slice()], Mipmap::Allocated,
+                 Bind::SHADER_RESOURCE | Bind::RENDER_TARGET)
+            }
+            MipmapGeneration::Cpu => {
+                cpu_levels = downscale_levels(img, levels);
+                let data = Some(img.as_raw().as_slice()).into_iter()
+                    .chain(cpu_levels.iter().map(|level| level.as_raw().as_slice()))
+                    .collect();
+                (data, Mipmap::Provided, Bind::SHADER_RESOURCE)
+            }
+        };
+
+        let info = Info {
+            kind: Kind::D2(w, h, AaMode::Single),
+            levels,
+            format: R8_G8_B8_A8::get_surface_type(),
+            bind,
+            usage: Usage::Dynamic,
+        };
+        let factory = &mut context.factory;
+        let raw = factory.create_texture_raw(info, Some(ChannelType::Srgb),
+                                             Some((&data, mipmap)))?;
+        let surface: gfx::handle::Texture<R, R8_G8_B8_A8> = Typed::new(raw);
+        let view = factory.view_texture_as_shader_resource::<Srgba8>(
+            &surface, (0, levels - 1), gfx::format::Swizzle::new())?;
+        let sampler = factory.create_sampler(mipmap_sampler_info(settings));
+        if generation == MipmapGeneration::Gpu {
+            context.encoder.generate_mipmap(&view);
+        }
+        Ok(Texture { surface, sampler, view })
+    }
+}
+
+// Returns the number of levels down to 1x1 pixels.
+fn mip_levels(width: u32, height: u32) -> u8 {
+    (32 - width.max(height).leading_zeros()) as u8
+}
+
+// Halves the image repeatedly, returning every level after the first.
+fn downscale_levels(img: &RgbaImage, levels: u8) -> Vec<RgbaImage> {
+    let mut result: Vec<RgbaImage> = Vec::with_capacity(levels as usize - 1);
+    for _ in 1..levels {
+        let next = {
+            let prev = result.last().unwrap_or(img);
+            let (w, h) = prev.dimensions();
+            imageops::resize(prev, (w / 2).max(1), (h / 2).max(1),
+                             imageops::FilterType::Triangle)
+        };
+        result.push(next);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_go_down_to_one_pixel() {
+        assert_eq!(mip_levels(1, 1), 1);
+        assert_eq!(mip_levels(2, 2), 2);
+        assert_eq!(mip_levels(5, 3), 3);
+        assert_eq!(mip_levels(3, 5), 3);
+        assert_eq!(mip_levels(640, 480), 10);
+        assert_eq!(mip_levels(1024, 1), 11);
+    }
+
+    #[test]
+    fn downscaled_levels_end_at_one_pixel() {
+        let img = RgbaImage::new(5, 3);
+        let sizes: Vec<(u32, u32)> = downscale_levels(&img, mip_levels(5, 3))
+            .iter().map(|level| level.dimensions()).collect();
+        assert_eq!(sizes, vec![(2, 1), (1, 1)]);
+
+        let img = RgbaImage::new(8, 2);
+        let sizes: Vec<(u32, u32)> = downscale_levels(&img, mip_levels(8, 2))
+            .iter().map(|level| level.dimensions()).collect();
+        assert_eq!(sizes, vec![(4, 1), (2, 1), (1, 1)]);
+    }
+}
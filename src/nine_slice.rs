@@ -0,0 +1,197 @@
+//! Nine-slice rendering of images for scalable UI frames.
+
+use graphics::{DrawState, Graphics, ImageSize};
+use graphics::math::Matrix2d;
+use graphics::triangulation::{rect_tri_list_uv, rect_tri_list_xy};
+use graphics::types::{Color, Rectangle, SourceRectangle};
+use graphics::BACK_END_MAX_VERTEX_COUNT as BUFFER_SIZE;
+
+/// Draws an image split into nine slices by border insets.
+///
+/// Corners keep their size, edges stretch or tile along one axis,
+/// and the center stretches to fill the rest.
+/// All slices are emitted in one `tri_list_uv` call,
+/// which `GfxGraphics` renders in a single draw call.
+#[derive(Copy, Clone, Debug)]
+pub struct NineSlice {
+    /// The color.
+    pub color: Color,
+    /// Border insets `[left, top, right, bottom]` in texture pixels.
+    pub insets: [f64; 4],
+    /// The source rectangle in the texture, or the whole texture if `None`.
+    pub source_rectangle: Option<SourceRectangle>,
+    /// Whether to repeat edges instead of stretching them.
+    pub tile_edges: bool,
+}
+
+impl NineSlice {
+    /// Creates a new nine-slice image with white color and stretched edges.
+    pub fn new(insets: [f64; 4]) -> NineSlice {
+        NineSlice {
+            color: [1.0; 4],
+            insets,
+            source_rectangle: None,
+            tile_edges: false,
+        }
+    }
+
+    /// Sets color.
+    pub fn color(mut self, value: Color) -> Self {
+        self.color = value;
+        self
+    }
+
+    /// Sets source rectangle.
+    pub fn src_rect(mut self, value: SourceRectangle) -> Self {
+        self.source_rectangle = Some(value);
+        self
+    }
+
+    /// Sets whether edges are tiled.
+    pub fn tile_edges(mut self, value: bool) -> Self {
+        self.tile_edges = value;
+        self
+    }
+
+    /// Draws the image in a rectangle.
+    ///
+    /// When the rectangle is smaller than the insets,
+    /// the borders are scaled down to fit.
+    /// Insets larger than the source rectangle are scaled down the same way.
+    pub fn draw<G>(
+        &self,
+        texture: &<G as Graphics>::Texture,
+        rect: Rectangle,
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        g: &mut G
+    )
+        where G: Graphics
+    {
+        let src = self.source_rectangle.unwrap_or_else(|| {
+            let (w, h) = texture.get_size();
+            [0.0, 0.0, w as f64, h as f64]
+        });
+        let [left, top, right, bottom] = self.insets;
+        let xs = slices(src[0], src[2], left, right, rect[0], rect[2]);
+        let ys = slices(src[1], src[3], top, bottom, rect[1], rect[3]);
+
+        let mut vertices: Vec<[f32; 2]> = Vec::with_capacity(9 * 6);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(9 * 6);
+        for (j, &y) in ys.iter().enumerate() {
+            for (i, &x) in xs.iter().enumerate() {
+                // Edges are the slices between two corners.
+                let tile_x = self.tile_edges && i == 1 && j != 1;
+                let tile_y = self.tile_edges && j == 1 && i != 1;
+                for (sx, dx) in tiles(x, tile_x) {
+                    for (sy, dy) in tiles(y, tile_y) {
+                        if dx[1] <= 0.0 || dy[1] <= 0.0 { continue; }
+                        vertices.extend_from_slice(&rect_tri_list_xy(transform,
+                            [dx[0], dy[0], dx[1], dy[1]]));
+                        uvs.extend_from_slice(&rect_tri_list_uv(texture,
+                            [sx[0], sy[0], sx[1], sy[1]]));
+                    }
+                }
+            }
+        }
+
+        // Keep chunks a multiple of whole quads.
+        let chunk = BUFFER_SIZE / 6 * 6;
+        g.tri_list_uv(draw_state, &self.color, texture, |f| {
+            for (v, uv) in vertices.chunks(chunk).zip(uvs.chunks(chunk)) {
+                f(v, uv);
+            }
+        });
+    }
+}
+
+// A slice along one axis as source and destination `[start, length]`.
+type Slice = ([f64; 2], [f64; 2]);
+
+// Splits source and destination ranges by insets into three slices.
+fn slices(
+    src: f64,
+    src_len: f64,
+    inset_start: f64,
+    inset_end: f64,
+    dst: f64,
+    dst_len: f64
+) -> [Slice; 3] {
+    let (src_start, src_end) = fit(inset_start, inset_end, src_len);
+    let (start, end) = fit(inset_start, inset_end, dst_len);
+    [
+        ([src, src_start], [dst, start]),
+        ([src + src_start, src_len - src_start - src_end],
+         [dst + start, dst_len - start - end]),
+        ([src + src_len - src_end, src_end], [dst + dst_len - end, end]),
+    ]
+}
+
+// Scales down insets that do not fit in a length.
+fn fit(start: f64, end: f64, len: f64) -> (f64, f64) {
+    if start + end > len && start + end > 0.0 {
+        let scale = len.max(0.0) / (start + end);
+        (start * scale, end * scale)
+    } else {
+        (start, end)
+    }
+}
+
+// Repeats a slice at source size to fill its destination,
+// cutting the last tile short.
+//
+// Slices thinner than a texture pixel are stretched instead.
+fn tiles(slice: Slice, tile: bool) -> Vec<Slice> {
+    let (src, dst) = slice;
+    if !tile || src[1] < 1.0 {
+        return vec![slice];
+    }
+    let mut tiles = vec![];
+    let mut pos = 0.0;
+    while pos < dst[1] {
+        let len = src[1].min(dst[1] - pos);
+        tiles.push(([src[0], len], [dst[0] + pos, len]));
+        pos += len;
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_keep_their_size() {
+        let [start, center, end] = slices(0.0, 30.0, 10.0, 5.0, 100.0, 200.0);
+        assert_eq!(start, ([0.0, 10.0], [100.0, 10.0]));
+        assert_eq!(center, ([10.0, 15.0], [110.0, 185.0]));
+        assert_eq!(end, ([25.0, 5.0], [295.0, 5.0]));
+    }
+
+    #[test]
+    fn insets_larger_than_destination_are_scaled_down() {
+        let [start, center, end] = slices(0.0, 30.0, 10.0, 30.0, 0.0, 20.0);
+        assert_eq!(start, ([0.0, 7.5], [0.0, 5.0]));
+        assert_eq!(center, ([7.5, 0.0], [5.0, 0.0]));
+        assert_eq!(end, ([7.5, 22.5], [5.0, 15.0]));
+    }
+
+    #[test]
+    fn tiles_cut_the_last_tile_short() {
+        let tiled = tiles(([10.0, 4.0], [0.0, 10.0]), true);
+        assert_eq!(tiled, vec![
+            ([10.0, 4.0], [0.0, 4.0]),
+            ([10.0, 4.0], [4.0, 4.0]),
+            ([10.0, 2.0], [8.0, 2.0]),
+        ]);
+        assert_eq!(tiles(([10.0, 4.0], [0.0, 10.0]), false).len(), 1);
+        assert_eq!(tiles(([10.0, 0.0], [0.0, 10.0]), true).len(), 1);
+    }
+
+    #[test]
+    fn slices_thinner_than_a_pixel_are_stretched() {
+        let slice = ([10.0, 1e-9], [0.0, 1000.0]);
+        assert_eq!(tiles(slice, true), vec![slice]);
+        assert_eq!(tiles(([10.0, 1.0], [0.0, 3.0]), true).len(), 3);
+    }
+}
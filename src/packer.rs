@@ -0,0 +1,96 @@
+//! Rectangle packing for texture atlases.
+
+// A row of tiles with the height of its tallest tile.
+struct Shelf {
+    y: u32,
+    height: u32,
+    // The offset of the next tile.
+    x: u32,
+}
+
+/// Packs rectangles into a fixed size area using shelves.
+///
+/// Tiles are placed left to right in rows.
+/// A new row is started when no existing row has room for a tile.
+/// Works best with tiles of similar height, such as glyphs and sprites.
+pub(crate) struct ShelfPacker {
+    size: [u32; 2],
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    /// Creates a new packer for an area.
+    pub(crate) fn new(size: [u32; 2]) -> ShelfPacker {
+        ShelfPacker {
+            size,
+            shelves: vec![],
+        }
+    }
+
+    /// Finds room for a tile and returns its offset.
+    ///
+    /// Returns `None` if there is not enough room left.
+    pub(crate) fn pack(&mut self, size: [u32; 2]) -> Option<[u32; 2]> {
+        if size[0] > self.size[0] || size[1] > self.size[1] {
+            return None;
+        }
+
+        // Pick the lowest shelf that fits to reduce wasted space.
+        let mut best: Option<usize> = None;
+        for (i, shelf) in self.shelves.iter().enumerate() {
+            if shelf.height >= size[1] && self.size[0] - shelf.x >= size[0] {
+                match best {
+                    Some(j) if self.shelves[j].height <= shelf.height => {}
+                    _ => best = Some(i),
+                }
+            }
+        }
+        if let Some(i) = best {
+            let shelf = &mut self.shelves[i];
+            let offset = [shelf.x, shelf.y];
+            shelf.x += size[0];
+            return Some(offset);
+        }
+
+        let y = self.shelves.last().map(|s| s.y + s.height).unwrap_or(0);
+        if self.size[1] - y < size[1] {
+            return None;
+        }
+        self.shelves.push(Shelf { y, height: size[1], x: size[0] });
+        Some([0, y])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_fill_rows_left_to_right() {
+        let mut packer = ShelfPacker::new([10, 10]);
+        assert_eq!(packer.pack([4, 3]), Some([0, 0]));
+        assert_eq!(packer.pack([4, 3]), Some([4, 0]));
+        // No room left in the first row.
+        assert_eq!(packer.pack([4, 3]), Some([0, 3]));
+    }
+
+    #[test]
+    fn tiles_use_the_lowest_row_that_fits() {
+        let mut packer = ShelfPacker::new([10, 20]);
+        assert_eq!(packer.pack([2, 8]), Some([0, 0]));
+        assert_eq!(packer.pack([2, 9]), Some([0, 8]));
+        // Both rows fit, the shorter one wastes less space.
+        assert_eq!(packer.pack([2, 3]), Some([2, 0]));
+        assert_eq!(packer.pack([2, 9]), Some([2, 8]));
+    }
+
+    #[test]
+    fn tiles_that_do_not_fit_are_rejected() {
+        let mut packer = ShelfPacker::new([10, 10]);
+        assert_eq!(packer.pack([11, 1]), None);
+        assert_eq!(packer.pack([1, 11]), None);
+        assert_eq!(packer.pack([10, 6]), Some([0, 0]));
+        assert_eq!(packer.pack([10, 5]), None);
+        assert_eq!(packer.pack([10, 4]), Some([0, 6]));
+    }
+}
@@ -0,0 +1,144 @@
+//! Offscreen render targets.
+
+use gfx;
+use gfx::format::{ChannelType, DepthStencil, Srgba8, R8_G8_B8_A8, D24_S8};
+use gfx::handle::{DepthStencilView, RenderTargetView, ShaderResourceView};
+use gfx::memory::{Bind, Usage};
+use gfx::texture::{AaMode, Kind, NumSamples};
+use graphics::Viewport;
+use sampler::create_sampler;
+use {ImageSize, Texture, TextureSettings};
+
+/// An offscreen target for rendering 2D graphics with `Gfx2d::draw`.
+///
+/// Pass `output_color` and `output_stencil` to `Gfx2d::draw`,
+/// then call `Gfx2d::resolve` to make the result available through `texture`.
+///
+/// When created with more than one sample, the target renders into
+/// multisampled surfaces which must be resolved before sampling.
+/// A single sampled target renders directly into `texture`,
+/// and resolving is a no-op.
+pub struct RenderTarget<R: gfx::Resources> {
+    /// The color output to pass to `Gfx2d::draw`.
+    pub output_color: RenderTargetView<R, Srgba8>,
+    /// The stencil output to pass to `Gfx2d::draw`.
+    pub output_stencil: DepthStencilView<R, DepthStencil>,
+    texture: Texture<R>,
+    multisample: Option<Multisample<R>>,
+}
+
+// Stores the extra surfaces used by multisampled targets.
+pub(crate) struct Multisample<R: gfx::Resources> {
+    // View of the multisampled color surface.
+    pub(crate) view: ShaderResourceView<R, [f32; 4]>,
+    // The render target of the resolved texture.
+    pub(crate) resolve: RenderTargetView<R, Srgba8>,
+    pub(crate) samples: NumSamples,
+}
+
+impl<R: gfx::Resources> RenderTarget<R> {
+    /// Creates a new render target.
+    ///
+    /// Uses multisampling when `samples` is greater than 1.
+    /// The texture settings are used for the sampler of the resolved texture.
+    pub fn new<F>(
+        factory: &mut F,
+        width: u16,
+        height: u16,
+        samples: NumSamples,
+        settings: &TextureSettings
+    ) -> Result<Self, gfx::CombinedError>
+        where F: gfx::Factory<R>
+    {
+        let single = Kind::D2(width, height, AaMode::Single);
+        let aa_mode: AaMode = samples.into();
+        let surface = factory.create_texture::<R8_G8_B8_A8>(
+            single,
+            1,
+            Bind::SHADER_RESOURCE | Bind::RENDER_TARGET,
+            Usage::Data,
+            Some(ChannelType::Srgb)
+        )?;
+        let view = factory.view_texture_as_shader_resource::<Srgba8>(
+            &surface, (0, 0), gfx::format::Swizzle::new())?;
+        let texture_target = factory.view_texture_as_render_target::<Srgba8>(
+            &surface, 0, None)?;
+        let sampler = create_sampler(factory, settings);
+        let texture = Texture { surface, sampler, view };
+
+        let stencil = factory.create_texture::<D24_S8>(
+            Kind::D2(width, height, aa_mode),
+            1,
+            Bind::DEPTH_STENCIL,
+            Usage::Data,
+            Some(ChannelType::Unorm)
+        )?;
+        let output_stencil = factory.view_texture_as_depth_stencil_trivial(&stencil)?;
+
+        let (output_color, multisample) = match aa_mode {
+            AaMode::Multi(samples) => {
+                let color = factory.create_texture::<R8_G8_B8_A8>(
+                    Kind::D2(width, height, aa_mode),
+                    1,
+                    Bind::SHADER_RESOURCE | Bind::RENDER_TARGET,
+                    Usage::Data,
+                    Some(ChannelType::Srgb)
+                )?;
+                let view = factory.view_texture_as_shader_resource::<Srgba8>(
+                    &color, (0, 0), gfx::format::Swizzle::new())?;
+                let output_color = factory.view_texture_as_render_target::<Srgba8>(
+                    &color, 0, None)?;
+                (output_color, Some(Multisample {
+                    view,
+                    resolve: texture_target,
+                    samples,
+                }))
+            }
+            _ => (texture_target, None),
+        };
+
+        Ok(RenderTarget {
+            output_color,
+            output_stencil,
+            texture,
+            multisample,
+        })
+    }
+
+    /// Returns the texture containing the resolved result.
+    ///
+    /// For multisampled targets, call `Gfx2d::resolve` after drawing.
+    pub fn texture(&self) -> &Texture<R> {
+        &self.texture
+    }
+
+    /// Returns the number of samples per pixel.
+    pub fn samples(&self) -> NumSamples {
+        self.multisample.as_ref().map(|m| m.samples).unwrap_or(1)
+    }
+
+    /// Returns true if the target needs to be resolved before sampling.
+    pub fn is_multisampled(&self) -> bool {
+        self.multisample.is_some()
+    }
+
+    /// Returns a viewport covering the whole target.
+    pub fn viewport(&self) -> Viewport {
+        let (w, h) = self.texture.get_size();
+        Viewport {
+            rect: [0, 0, w as i32, h as i32],
+            draw_size: [w, h],
+            window_size: [w as f64, h as f64],
+        }
+    }
+
+    pub(crate) fn multisample(&self) -> Option<&Multisample<R>> {
+        self.multisample.as_ref()
+    }
+}
+
+impl<R: gfx::Resources> ImageSize for RenderTarget<R> {
+    fn get_size(&self) -> (u32, u32) {
+        self.texture.get_size()
+    }
+}
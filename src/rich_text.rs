@@ -0,0 +1,200 @@
+//! Rich text mixing colors, fonts and sizes.
+
+use gfx;
+use graphics::{DrawState, Graphics};
+use graphics::color::gamma_srgb_to_linear;
+use graphics::math::Matrix2d;
+use graphics::types::{Color, FontSize};
+use graphics::BACK_END_MAX_VERTEX_COUNT as BUFFER_SIZE;
+
+use back_end::GfxGraphics;
+use glyph::{FontId, AtlasGlyphCache};
+use layout::{self, Batch, Style, TextAlign, TextBlock};
+use Error;
+
+/// A piece of text with its own color, font size and font.
+#[derive(Copy, Clone, Debug)]
+pub struct Span<'t> {
+    /// The text.
+    pub text: &'t str,
+    /// The color.
+    pub color: Color,
+    /// The font size.
+    pub font_size: FontSize,
+    /// The font.
+    pub font: FontId,
+}
+
+impl<'t> Span<'t> {
+    /// Creates a new span with black color using the default font.
+    pub fn new(text: &'t str, font_size: FontSize) -> Span<'t> {
+        Span {
+            text,
+            color: [0.0, 0.0, 0.0, 1.0],
+            font_size,
+            font: FontId::default(),
+        }
+    }
+
+    /// Sets color.
+    pub fn color(mut self, value: Color) -> Self {
+        self.color = value;
+        self
+    }
+
+    /// Sets font.
+    pub fn font(mut self, value: FontId) -> Self {
+        self.font = value;
+        self
+    }
+}
+
+/// Lays out and renders spans as one text.
+///
+/// Lines wrap across spans, and the height of each line fits
+/// the largest font on it.
+/// Glyphs sharing an atlas texture are rendered in one call,
+/// using vertex colors for the span colors.
+#[derive(Copy, Clone, Debug)]
+pub struct RichText {
+    /// The width to wrap lines at, if any.
+    pub max_width: Option<f64>,
+    /// The alignment of lines.
+    pub align: TextAlign,
+    /// The distance between baselines relative to the font line height.
+    pub line_spacing: f64,
+}
+
+impl RichText {
+    /// Creates a new left aligned rich text with no wrapping.
+    pub fn new() -> RichText {
+        RichText {
+            max_width: None,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+        }
+    }
+
+    /// Sets the width to wrap lines at.
+    pub fn max_width(mut self, value: f64) -> Self {
+        self.max_width = Some(value);
+        self
+    }
+
+    /// Sets alignment.
+    pub fn align(mut self, value: TextAlign) -> Self {
+        self.align = value;
+        self
+    }
+
+    /// Sets line spacing.
+    pub fn line_spacing(mut self, value: f64) -> Self {
+        self.line_spacing = value;
+        self
+    }
+
+    /// Returns the width and height of spans.
+    pub fn measure<F, R, C>(
+        &self,
+        spans: &[Span],
+        cache: &mut AtlasGlyphCache<F, R, C>
+    ) -> Result<[f64; 2], Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>
+    {
+        Ok(self.layout(spans, cache)?.size)
+    }
+
+    /// Positions spans with the upper left corner at the origin.
+    ///
+    /// The `span` field of positioned characters is the index into `spans`.
+    pub fn layout<F, R, C>(
+        &self,
+        spans: &[Span],
+        cache: &mut AtlasGlyphCache<F, R, C>
+    ) -> Result<TextBlock, Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>
+    {
+        let styles: Vec<Style> = spans.iter().map(|span| Style {
+            font: span.font,
+            font_size: span.font_size,
+        }).collect();
+        let chars = spans.iter().enumerate()
+            .flat_map(|(i, span)| span.text.chars().map(move |ch| (ch, i)));
+        layout::layout(chars, &styles, self.max_width, self.align, self.line_spacing, cache)
+    }
+
+    /// Draws spans with the upper left corner at the origin.
+    pub fn draw<F, R, C, C2>(
+        &self,
+        spans: &[Span],
+        cache: &mut AtlasGlyphCache<F, R, C>,
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        g: &mut GfxGraphics<R, C2>
+    ) -> Result<(), Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>,
+              C2: gfx::CommandBuffer<R>
+    {
+        let block = self.layout(spans, cache)?;
+        self.draw_block(&block, spans, cache, draw_state, transform, g)
+    }
+
+    /// Draws spans positioned by `layout`.
+    pub fn draw_block<F, R, C, C2>(
+        &self,
+        block: &TextBlock,
+        spans: &[Span],
+        cache: &mut AtlasGlyphCache<F, R, C>,
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        g: &mut GfxGraphics<R, C2>
+    ) -> Result<(), Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>,
+              C2: gfx::CommandBuffer<R>
+    {
+        let mut batches: Vec<Batch> = vec![];
+        // Vertex colors per atlas, in linear space like uniform colors.
+        let mut colors: Vec<Vec<[f32; 4]>> = vec![];
+        for c in &block.chars {
+            let span = &spans[c.span];
+            let style = Style {
+                font: span.font,
+                font_size: span.font_size,
+            };
+            let atlas = layout::push_glyph(c, style, transform, cache, &mut batches)?;
+            if colors.len() <= atlas {
+                colors.resize(atlas + 1, vec![]);
+            }
+            colors[atlas].extend_from_slice(&[gamma_srgb_to_linear(span.color); 6]);
+        }
+        cache.flush_uploads(g);
+
+        // Keep chunks a multiple of whole quads.
+        let chunk = BUFFER_SIZE / 6 * 6;
+        for (i, ((vertices, uvs), colors)) in batches.iter().zip(&colors).enumerate() {
+            if vertices.is_empty() { continue; }
+            g.tri_list_uv_c(draw_state, cache.atlas(i), |f| {
+                for ((v, uv), c) in vertices.chunks(chunk)
+                    .zip(uvs.chunks(chunk))
+                    .zip(colors.chunks(chunk)) {
+                    f(v, uv, c);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for RichText {
+    fn default() -> RichText {
+        RichText::new()
+    }
+}
@@ -0,0 +1,51 @@
+//! Conversion of texture settings into gfx samplers.
+
+use gfx;
+use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
+use {Filter, TextureSettings, Wrap};
+
+// Converts a wrap mode into the gfx equivalent.
+fn wrap_mode(wrap: Wrap) -> WrapMode {
+    match wrap {
+        Wrap::ClampToEdge => WrapMode::Clamp,
+        Wrap::ClampToBorder => WrapMode::Border,
+        Wrap::Repeat => WrapMode::Tile,
+        Wrap::MirroredRepeat => WrapMode::Mirror,
+    }
+}
+
+/// Returns the sampler info matching texture settings.
+///
+/// This uses the same conversion as textures created through `TextureContext`.
+pub(crate) fn sampler_info(settings: &TextureSettings) -> SamplerInfo {
+    let filter_method = match settings.get_mag() {
+        Filter::Nearest => FilterMethod::Scale,
+        Filter::Linear => FilterMethod::Bilinear,
+    };
+    let mut info = SamplerInfo::new(filter_method, wrap_mode(settings.get_wrap_u()));
+    info.wrap_mode.1 = wrap_mode(settings.get_wrap_v());
+    info.border = settings.get_border_color().into();
+    info
+}
+
+/// Returns the sampler info for textures with a mip chain.
+///
+/// gfx has one filter for minifying and magnifying, so this picks
+/// trilinear filtering if the minify or mipmap filter is linear.
+pub(crate) fn mipmap_sampler_info(settings: &TextureSettings) -> SamplerInfo {
+    let mut info = sampler_info(settings);
+    info.filter = match (settings.get_min(), settings.get_mipmap()) {
+        (Filter::Nearest, Filter::Nearest) => FilterMethod::Mipmap,
+        _ => FilterMethod::Trilinear,
+    };
+    info
+}
+
+/// Creates a sampler matching texture settings.
+pub(crate) fn create_sampler<R, F>(factory: &mut F, settings: &TextureSettings)
+    -> gfx::handle::Sampler<R>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>
+{
+    factory.create_sampler(sampler_info(settings))
+}
@@ -0,0 +1,298 @@
+//! Signed distance field text rendering.
+//!
+//! Each glyph is rasterized once at `SDF_BASE_SIZE` into a distance field
+//! stored in an atlas texture.
+//! The distance field is rendered with `GfxGraphics::tri_list_uv_sdf`,
+//! which keeps edges crisp at any scale and rotation.
+//! Enable it once with `Gfx2d::enable_sdf`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use gfx;
+use graphics::DrawState;
+use graphics::math::Matrix2d;
+use graphics::types::{Color, FontSize};
+use graphics::triangulation::{rect_tri_list_uv, rect_tri_list_xy};
+use graphics::BACK_END_MAX_VERTEX_COUNT as BUFFER_SIZE;
+use rusttype as rt;
+
+use back_end::GfxGraphics;
+use atlas::AtlasPages;
+use {Error, Texture, TextureContext, TextureSettings};
+
+/// The size in pixels that glyphs are rasterized at.
+pub const SDF_BASE_SIZE: u32 = 48;
+
+/// The maximum distance in pixels stored in the distance field.
+pub const SDF_SPREAD: u32 = 6;
+
+/// The size of atlas textures storing distance fields.
+pub const SDF_ATLAS_SIZE: [u32; 2] = [1024; 2];
+
+// Layout of a glyph at base size.
+#[derive(Clone, Copy)]
+struct Glyph {
+    // Offset from the pen position to the upper left corner.
+    offset: [f64; 2],
+    size: [f64; 2],
+    advance: f64,
+    atlas_offset: [f64; 2],
+    atlas: usize,
+}
+
+/// Stores glyphs as signed distance fields in atlas textures.
+pub struct SdfGlyphCache<'a, F, R, C>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    /// The font.
+    pub font: rt::Font<'a>,
+    /// The context used to create atlas textures.
+    ///
+    /// New glyphs are written by `SdfText::draw` before drawing them,
+    /// so the encoder of the context does not need to be flushed.
+    pub factory: TextureContext<F, R, C>,
+    atlases: AtlasPages<R>,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl<'a, F, R, C> SdfGlyphCache<'a, F, R, C>
+    where F: gfx::Factory<R>,
+          R: gfx::Resources,
+          C: gfx::CommandBuffer<R>
+{
+    /// Creates a new cache from a font.
+    pub fn from_font(font: rt::Font<'a>, factory: TextureContext<F, R, C>) -> Self {
+        SdfGlyphCache {
+            font,
+            factory,
+            atlases: AtlasPages::new(SDF_ATLAS_SIZE, TextureSettings::new()),
+            glyphs: HashMap::new(),
+        }
+    }
+
+    /// Creates a new cache loading a font from file.
+    pub fn new<P>(
+        font: P,
+        factory: TextureContext<F, R, C>
+    ) -> io::Result<SdfGlyphCache<'static, F, R, C>>
+        where P: AsRef<Path>
+    {
+        let mut file_buffer = Vec::new();
+        File::open(font)?.read_to_end(&mut file_buffer)?;
+        let font = rt::Font::try_from_vec(file_buffer).ok_or_else(||
+            io::Error::new(io::ErrorKind::InvalidData, "invalid font"))?;
+        Ok(SdfGlyphCache::from_font(font, factory))
+    }
+
+    /// Loads all characters in the `chars` iterator.
+    pub fn preload_chars<I>(&mut self, chars: I) -> Result<(), Error>
+        where I: Iterator<Item = char>
+    {
+        for ch in chars {
+            self.glyph(ch)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the width of some text at a font size.
+    pub fn width(&mut self, font_size: FontSize, text: &str) -> Result<f64, Error> {
+        let scale = font_scale(font_size);
+        let mut width = 0.0;
+        for ch in text.chars() {
+            width += self.glyph(ch)?.advance * scale;
+        }
+        Ok(width)
+    }
+
+    /// Returns the atlas textures.
+    pub fn atlases(&self) -> impl Iterator<Item = &Texture<R>> {
+        self.atlases.textures()
+    }
+
+    /// Writes new glyphs through the encoder of `GfxGraphics`.
+    ///
+    /// `SdfText::draw` calls this before drawing,
+    /// so it is only needed for glyphs loaded with `width`.
+    pub fn flush_uploads<C2>(&mut self, g: &mut GfxGraphics<R, C2>)
+        where C2: gfx::CommandBuffer<R>
+    {
+        self.atlases.flush_uploads(g.encoder);
+    }
+
+    // Returns a glyph, rasterizing it if needed.
+    fn glyph(&mut self, ch: char) -> Result<Glyph, Error> {
+        if let Some(&glyph) = self.glyphs.get(&ch) {
+            return Ok(glyph);
+        }
+
+        let pad = SDF_SPREAD as i32;
+        let glyph = self.font.glyph(ch)
+            .scaled(rt::Scale::uniform(SDF_BASE_SIZE as f32));
+        let advance = glyph.h_metrics().advance_width as f64;
+        let glyph = glyph.positioned(rt::point(0.0, 0.0));
+        let bb = glyph.pixel_bounding_box().unwrap_or(rt::Rect {
+            min: rt::Point { x: 0, y: 0 },
+            max: rt::Point { x: 0, y: 0 },
+        });
+        let size = [
+            (bb.width() + 2 * pad) as u32,
+            (bb.height() + 2 * pad) as u32,
+        ];
+
+        let mut coverage = vec![0.0; (size[0] * size[1]) as usize];
+        glyph.draw(|x, y, v| {
+            let pos = (x + pad as u32) + (y + pad as u32) * size[0];
+            coverage[pos as usize] = v;
+        });
+        let field = distance_field(&coverage, size, SDF_SPREAD);
+
+        let (atlas, offset) = self.atlases.add(&mut self.factory, size, &field)?;
+
+        let glyph = Glyph {
+            offset: [(bb.min.x - pad) as f64, -(bb.min.y - pad) as f64],
+            size: [size[0] as f64, size[1] as f64],
+            advance,
+            atlas_offset: [offset[0] as f64, offset[1] as f64],
+            atlas,
+        };
+        self.glyphs.insert(ch, glyph);
+        Ok(glyph)
+    }
+}
+
+// Converts a font size in points to scale relative to the base size.
+//
+// Uses the same conversion from points to pixels as `AtlasGlyphCache`.
+fn font_scale(font_size: FontSize) -> f64 {
+    (font_size as f64 * 1.333).round() / SDF_BASE_SIZE as f64
+}
+
+// Computes a distance field from glyph coverage.
+//
+// Returns white RGBA pixels with the distance in the alpha channel,
+// where 0.5 is on the edge and values above are inside.
+fn distance_field(coverage: &[f32], size: [u32; 2], spread: u32) -> Vec<u8> {
+    let (w, h) = (size[0] as i32, size[1] as i32);
+    let r = spread as i32;
+    let inside = |x: i32, y: i32| coverage[(x + y * w) as usize] >= 0.5;
+    let mut field = Vec::with_capacity(coverage.len() * 4);
+    for y in 0..h {
+        for x in 0..w {
+            let is_inside = inside(x, y);
+            // Search for the closest pixel on the other side of the edge.
+            let mut min_sq = (r * r) as f32;
+            for dy in -r..r + 1 {
+                for dx in -r..r + 1 {
+                    let (sx, sy) = (x + dx, y + dy);
+                    let other = if sx < 0 || sy < 0 || sx >= w || sy >= h {
+                        false
+                    } else {
+                        inside(sx, sy)
+                    };
+                    if other != is_inside {
+                        min_sq = min_sq.min((dx * dx + dy * dy) as f32);
+                    }
+                }
+            }
+            // The edge lies half way between pixel centers.
+            let dist = (min_sq.sqrt() - 0.5).max(0.0);
+            let signed = if is_inside { dist } else { -dist };
+            let value = 0.5 + signed / (2.0 * spread as f32);
+            field.extend_from_slice(&[255, 255, 255,
+                (value.clamp(0.0, 1.0) * 255.0).round() as u8]);
+        }
+    }
+    field
+}
+
+// Vertices and texture coordinates of quads sharing an atlas.
+type Batch = (Vec<[f32; 2]>, Vec<[f32; 2]>);
+
+/// Renders text using a signed distance field glyph cache.
+#[derive(Copy, Clone)]
+pub struct SdfText {
+    /// The color.
+    pub color: Color,
+    /// The font size.
+    pub font_size: FontSize,
+}
+
+impl SdfText {
+    /// Creates a new text with black color.
+    pub fn new(font_size: FontSize) -> SdfText {
+        SdfText {
+            color: [0.0, 0.0, 0.0, 1.0],
+            font_size,
+        }
+    }
+
+    /// Creates a new colored text.
+    pub fn new_color(color: Color, font_size: FontSize) -> SdfText {
+        SdfText {
+            color,
+            font_size,
+        }
+    }
+
+    /// Draws text with the baseline starting at the origin.
+    ///
+    /// Glyphs sharing an atlas texture are rendered in one call.
+    pub fn draw<F, R, C, C2>(
+        &self,
+        text: &str,
+        cache: &mut SdfGlyphCache<F, R, C>,
+        draw_state: &DrawState,
+        transform: Matrix2d,
+        g: &mut GfxGraphics<R, C2>
+    ) -> Result<(), Error>
+        where F: gfx::Factory<R>,
+              R: gfx::Resources,
+              C: gfx::CommandBuffer<R>,
+              C2: gfx::CommandBuffer<R>
+    {
+        let scale = font_scale(self.font_size);
+        // Vertices and texture coordinates per atlas.
+        let mut batches: Vec<Batch> = vec![];
+        let mut x = 0.0;
+        for ch in text.chars() {
+            let glyph = cache.glyph(ch)?;
+            if batches.len() <= glyph.atlas {
+                batches.resize(glyph.atlas + 1, (vec![], vec![]));
+            }
+            let atlas = cache.atlases.texture(glyph.atlas);
+            let batch = &mut batches[glyph.atlas];
+            batch.0.extend_from_slice(&rect_tri_list_xy(transform, [
+                x + glyph.offset[0] * scale,
+                -glyph.offset[1] * scale,
+                glyph.size[0] * scale,
+                glyph.size[1] * scale,
+            ]));
+            batch.1.extend_from_slice(&rect_tri_list_uv(atlas, [
+                glyph.atlas_offset[0],
+                glyph.atlas_offset[1],
+                glyph.size[0],
+                glyph.size[1],
+            ]));
+            x += glyph.advance * scale;
+        }
+        cache.flush_uploads(g);
+
+        // Keep chunks a multiple of whole quads.
+        let chunk = BUFFER_SIZE / 6 * 6;
+        for (i, (vertices, uvs)) in batches.iter().enumerate() {
+            if vertices.is_empty() { continue; }
+            let atlas = cache.atlases.texture(i);
+            g.tri_list_uv_sdf(draw_state, &self.color, atlas, |f| {
+                for (v, uv) in vertices.chunks(chunk).zip(uvs.chunks(chunk)) {
+                    f(v, uv);
+                }
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! Statistics of frames rendered with `Gfx2d::draw`.
+
+use std::mem;
+use std::time::Duration;
+
+use graphics::DrawState;
+
+/// Number of draw calls per pipeline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DrawCalls {
+    /// Colored triangles.
+    pub colored: usize,
+    /// Textured triangles with a uniform color.
+    pub textured: usize,
+    /// Textured triangles with colors per vertex.
+    pub textured_color: usize,
+    /// Signed distance field textures.
+    pub sdf: usize,
+    /// Texture arrays.
+    pub array: usize,
+    /// Single and two channel textures.
+    pub channels: usize,
+    /// Triangles drawn as lines, see `Gfx2d::set_wireframe`.
+    pub wireframe: usize,
+}
+
+impl DrawCalls {
+    /// Returns the number of draw calls of all pipelines.
+    pub fn total(&self) -> usize {
+        self.colored + self.textured + self.textured_color +
+            self.sdf + self.array + self.channels + self.wireframe
+    }
+}
+
+/// Statistics of a frame rendered with `Gfx2d::draw`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Draw calls per pipeline.
+    pub draw_calls: DrawCalls,
+    /// Vertices written to the vertex buffers.
+    pub vertices: usize,
+    /// Number of times colored triangles were flushed.
+    pub colored_flushes: usize,
+    /// Changes of pipeline state between consecutive draw calls.
+    ///
+    /// A pipeline state is selected by pipeline, blend and stencil setting.
+    pub pso_switches: usize,
+    /// Changes of stencil setting between consecutive draw calls.
+    pub stencil_changes: usize,
+    /// Changes of scissor rectangle between consecutive draw calls.
+    pub scissor_changes: usize,
+    /// Bytes written to the position buffer.
+    pub bytes_pos: usize,
+    /// Bytes written to the color buffer.
+    pub bytes_color: usize,
+    /// Bytes written to the texture coordinate buffer.
+    pub bytes_uv: usize,
+}
+
+/// GPU time measured for a frame rendered with `Gfx2d::draw`.
+///
+/// Timer queries finish a few frames after drawing,
+/// so times are reported for an earlier frame.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GpuTimes {
+    /// Index of the measured frame, counting frames drawn with timing enabled.
+    pub frame: u64,
+    /// Time of the whole 2D pass.
+    pub pass: Duration,
+    /// Time of each flush of batched triangles, in drawing order.
+    pub flushes: Vec<Duration>,
+}
+
+/// The reason GPU times can not be reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpuTimesError {
+    /// GPU timing is not enabled with `Gfx2d::set_gpu_timing`.
+    Disabled,
+    /// The back-end can not record timer queries.
+    Unavailable,
+}
+
+/// The pipelines counted by `DrawCalls`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Pipeline {
+    Colored,
+    Textured,
+    TexturedColor,
+    Sdf,
+    Array,
+    Channels,
+    Wireframe,
+}
+
+/// Records statistics while drawing frames.
+pub(crate) struct StatsRecorder {
+    current: FrameStats,
+    last: FrameStats,
+    /// Pipeline and draw state of the previous draw call in the frame.
+    previous: Option<(Pipeline, DrawState)>,
+}
+
+impl StatsRecorder {
+    pub(crate) fn new() -> StatsRecorder {
+        StatsRecorder {
+            current: FrameStats::default(),
+            last: FrameStats::default(),
+            previous: None,
+        }
+    }
+
+    /// Starts recording a new frame.
+    pub(crate) fn begin_frame(&mut self) {
+        self.current = FrameStats::default();
+        self.previous = None;
+    }
+
+    /// Finishes the frame, making it available through `last`.
+    pub(crate) fn end_frame(&mut self) {
+        self.last = mem::take(&mut self.current);
+        self.previous = None;
+    }
+
+    /// Returns the statistics of the last finished frame.
+    pub(crate) fn last(&self) -> &FrameStats {
+        &self.last
+    }
+
+    /// Records vertices written to the buffers.
+    pub(crate) fn upload(&mut self, vertices: usize, bytes_pos: usize, bytes_color: usize,
+                         bytes_uv: usize) {
+        let stats = &mut self.current;
+        stats.vertices += vertices;
+        stats.bytes_pos += bytes_pos;
+        stats.bytes_color += bytes_color;
+        stats.bytes_uv += bytes_uv;
+    }
+
+    /// Records a flush of colored triangles.
+    pub(crate) fn colored_flush(&mut self) {
+        self.current.colored_flushes += 1;
+    }
+
+    /// Records a draw call and state changes since the previous one.
+    pub(crate) fn draw(&mut self, pipeline: Pipeline, draw_state: &DrawState) {
+        let stats = &mut self.current;
+        {
+            let calls = &mut stats.draw_calls;
+            match pipeline {
+                Pipeline::Colored => calls.colored += 1,
+                Pipeline::Textured => calls.textured += 1,
+                Pipeline::TexturedColor => calls.textured_color += 1,
+                Pipeline::Sdf => calls.sdf += 1,
+                Pipeline::Array => calls.array += 1,
+                Pipeline::Channels => calls.channels += 1,
+                Pipeline::Wireframe => calls.wireframe += 1,
+            }
+        }
+        if let Some((prev_pipeline, ref prev)) = self.previous {
+            if prev_pipeline != pipeline || prev.blend != draw_state.blend ||
+               prev.stencil != draw_state.stencil {
+                stats.pso_switches += 1;
+            }
+            if prev.stencil != draw_state.stencil {
+                stats.stencil_changes += 1;
+            }
+            if prev.scissor != draw_state.scissor {
+                stats.scissor_changes += 1;
+            }
+        }
+        self.previous = Some((pipeline, *draw_state));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_calls_and_state_changes_are_counted() {
+        let mut stats = StatsRecorder::new();
+        let clipped = DrawState::default().scissor([0, 0, 10, 10]);
+        stats.begin_frame();
+        stats.draw(Pipeline::Colored, &DrawState::default());
+        stats.draw(Pipeline::Colored, &DrawState::default());
+        stats.draw(Pipeline::Textured, &DrawState::default());
+        stats.draw(Pipeline::Textured, &clipped);
+        stats.draw(Pipeline::Textured, &DrawState::new_clip());
+        stats.colored_flush();
+        stats.upload(3, 24, 48, 0);
+        stats.upload(6, 48, 0, 48);
+        stats.end_frame();
+
+        let last = stats.last();
+        assert_eq!(last.draw_calls.colored, 2);
+        assert_eq!(last.draw_calls.textured, 3);
+        assert_eq!(last.draw_calls.total(), 5);
+        // Switching pipeline, and switching stencil.
+        assert_eq!(last.pso_switches, 2);
+        assert_eq!(last.stencil_changes, 1);
+        assert_eq!(last.scissor_changes, 2);
+        assert_eq!(last.colored_flushes, 1);
+        assert_eq!(last.vertices, 9);
+        assert_eq!([last.bytes_pos, last.bytes_color, last.bytes_uv], [72, 48, 48]);
+    }
+
+    #[test]
+    fn frames_are_counted_separately() {
+        let mut stats = StatsRecorder::new();
+        stats.begin_frame();
+        stats.draw(Pipeline::Colored, &DrawState::default());
+        stats.end_frame();
+        stats.begin_frame();
+        stats.draw(Pipeline::Sdf, &DrawState::new_clip());
+        assert_eq!(stats.last().draw_calls.colored, 1);
+        stats.end_frame();
+        assert_eq!(stats.last().draw_calls, DrawCalls { sdf: 1, ..DrawCalls::default() });
+        // The first call of a frame changes no state.
+        assert_eq!(stats.last().pso_switches, 0);
+        assert_eq!(stats.last().stencil_changes, 0);
+    }
+}
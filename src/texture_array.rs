@@ -0,0 +1,170 @@
+//! Arrays of same-size texture layers.
+
+use gfx;
+use gfx::format::{ChannelType, Rgba8, Srgba8, R8_G8_B8_A8};
+use gfx::handle::{Sampler, ShaderResourceView};
+use gfx::memory::{Bind, Usage};
+use gfx::texture::{AaMode, Kind};
+use image::RgbaImage;
+
+use compressed::texture_size;
+use sampler::create_sampler;
+use {Error, ImageSize, TextureContext, TextureSettings};
+
+/// A 2D texture array with layers of the same size.
+///
+/// Rendered with `GfxGraphics::tri_list_uv_layer` or `GfxGraphics::image_layer`,
+/// which batch triangles using different layers into one draw call.
+/// Drawing requires `Gfx2d::enable_texture_arrays`.
+/// Unlike atlases, layers never bleed into each other when filtering.
+///
+/// Layers are written through the encoder of the texture context,
+/// like `Texture::update`, so flush it before drawing new layers.
+pub struct TextureArray<R: gfx::Resources> {
+    surface: gfx::handle::Texture<R, R8_G8_B8_A8>,
+    view: ShaderResourceView<R, [f32; 4]>,
+    sampler: Sampler<R>,
+    size: [u32; 2],
+    layers: u32,
+}
+
+impl<R: gfx::Resources> TextureArray<R> {
+    /// Creates a new texture array with transparent layers.
+    ///
+    /// Flush the encoder of the context before drawing the array.
+    /// The size and number of layers must fit in 16 bits.
+    pub fn new<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        size: [u32; 2],
+        layers: u32,
+        settings: &TextureSettings
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        if layers == 0 || size[0] == 0 || size[1] == 0 {
+            return Err(Error::Image("Texture array can not be empty".into()));
+        }
+        let (w, h) = texture_size(size)?;
+        if layers > u16::MAX as u32 {
+            return Err(Error::Image(format!(
+                "Texture array can have at most {} layers, got {}", u16::MAX, layers)));
+        }
+        let factory = &mut context.factory;
+        let surface = factory.create_texture::<R8_G8_B8_A8>(
+            Kind::D2Array(w, h, layers as u16, AaMode::Single),
+            1,
+            Bind::SHADER_RESOURCE,
+            Usage::Dynamic,
+            Some(ChannelType::Srgb)
+        )?;
+        let view = factory.view_texture_as_shader_resource::<Srgba8>(
+            &surface, (0, 0), gfx::format::Swizzle::new())?;
+        let sampler = create_sampler(factory, settings);
+        let mut texture = TextureArray {
+            surface,
+            view,
+            sampler,
+            size,
+            layers,
+        };
+        let empty = vec![0; size[0] as usize * size[1] as usize * 4];
+        for layer in 0..layers {
+            texture.update_layer_memory(context, layer, &empty)?;
+        }
+        Ok(texture)
+    }
+
+    /// Creates a new texture array with one layer per image.
+    ///
+    /// All images must have the same size.
+    /// Flush the encoder of the context before drawing the array.
+    pub fn from_images<F, C>(
+        context: &mut TextureContext<F, R, C>,
+        images: &[RgbaImage],
+        settings: &TextureSettings
+    ) -> Result<Self, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        let size = match images.first() {
+            None => return Err(Error::Image("Texture array can not be empty".into())),
+            Some(image) => [image.width(), image.height()],
+        };
+        let mut texture = TextureArray::new(context, size, images.len() as u32, settings)?;
+        for (layer, image) in images.iter().enumerate() {
+            texture.update_layer(context, layer as u32, image)?;
+        }
+        Ok(texture)
+    }
+
+    /// Replaces a layer with an image of the same size.
+    ///
+    /// Fails if the layer is out of bounds.
+    /// The layer is written through the encoder of the context,
+    /// which must be flushed before drawing the new layer.
+    pub fn update_layer<F, C>(
+        &mut self,
+        context: &mut TextureContext<F, R, C>,
+        layer: u32,
+        image: &RgbaImage
+    ) -> Result<(), Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        if [image.width(), image.height()] != self.size {
+            return Err(Error::Image(format!(
+                "Expected image of size {:?} for texture array layer, got {:?}",
+                self.size, image.dimensions())));
+        }
+        self.update_layer_memory(context, layer, image)
+    }
+
+    // Writes a layer through the encoder of the context.
+    fn update_layer_memory<F, C>(
+        &mut self,
+        context: &mut TextureContext<F, R, C>,
+        layer: u32,
+        memory: &[u8]
+    ) -> Result<(), Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        if layer >= self.layers {
+            return Err(Error::Image(format!(
+                "Layer {} is out of bounds of texture array with {} layers", layer, self.layers)));
+        }
+        let img_info = gfx::texture::ImageInfoCommon {
+            xoffset: 0,
+            yoffset: 0,
+            zoffset: layer as u16,
+            width: self.size[0] as u16,
+            height: self.size[1] as u16,
+            depth: 1,
+            format: (),
+            mipmap: 0,
+        };
+        let data = gfx::memory::cast_slice(memory);
+        context.encoder.update_texture::<_, Rgba8>(&self.surface, None, img_info, data)?;
+        Ok(())
+    }
+
+    /// Returns the number of layers.
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub(crate) fn view(&self) -> &ShaderResourceView<R, [f32; 4]> {
+        &self.view
+    }
+
+    pub(crate) fn sampler(&self) -> &Sampler<R> {
+        &self.sampler
+    }
+}
+
+impl<R: gfx::Resources> ImageSize for TextureArray<R> {
+    fn get_size(&self) -> (u32, u32) {
+        (self.size[0], self.size[1])
+    }
+}
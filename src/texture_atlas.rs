@@ -0,0 +1,187 @@
+//! Packing of many small images into a few large textures.
+
+use gfx;
+use image::RgbaImage;
+
+use atlas::AtlasPages;
+use back_end::GfxGraphics;
+use texture_region::TextureRegion;
+use {Error, Texture, TextureContext, TextureSettings};
+
+/// The default size of textures in a `TextureAtlas`.
+pub const TEXTURE_ATLAS_SIZE: [u32; 2] = [2048; 2];
+
+/// A handle to an image packed into a `TextureAtlas`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AtlasImage {
+    page: usize,
+    rect: [u32; 4],
+}
+
+impl AtlasImage {
+    /// Returns the index of the atlas texture containing the image.
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    /// Returns the rectangle of the image in the atlas texture.
+    pub fn rect(&self) -> [u32; 4] {
+        self.rect
+    }
+
+    /// Returns the size of the image.
+    pub fn size(&self) -> [u32; 2] {
+        [self.rect[2], self.rect[3]]
+    }
+
+    /// Returns the source rectangle to use with `Image::src_rect`.
+    pub fn src_rect(&self) -> [f64; 4] {
+        [self.rect[0] as f64, self.rect[1] as f64,
+         self.rect[2] as f64, self.rect[3] as f64]
+    }
+}
+
+/// Packs images into one or more large textures.
+///
+/// Images drawn from the same atlas texture are batched by `GfxGraphics`
+/// into a single draw call.
+/// Added images are written to the atlas textures with `flush_uploads`.
+/// Borders of images are extruded into the padding around them,
+/// to avoid bleeding of neighbours with linear filtering.
+pub struct TextureAtlas<R: gfx::Resources> {
+    pages: AtlasPages<R>,
+    padding: u32,
+}
+
+impl<R: gfx::Resources> TextureAtlas<R> {
+    /// Creates a new empty atlas with one pixel padding.
+    ///
+    /// Textures are created when needed with `TEXTURE_ATLAS_SIZE`,
+    /// or larger to fit big images.
+    pub fn new(settings: TextureSettings) -> TextureAtlas<R> {
+        TextureAtlas {
+            pages: AtlasPages::new(TEXTURE_ATLAS_SIZE, settings),
+            padding: 1,
+        }
+    }
+
+    /// Sets the size of new atlas textures.
+    pub fn page_size(mut self, value: [u32; 2]) -> Self {
+        self.pages.set_size(value);
+        self
+    }
+
+    /// Sets the padding around images.
+    pub fn padding(mut self, value: u32) -> Self {
+        self.padding = value;
+        self
+    }
+
+    /// Adds an image to the atlas.
+    pub fn add<F, C>(
+        &mut self,
+        context: &mut TextureContext<F, R, C>,
+        image: &RgbaImage
+    ) -> Result<AtlasImage, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        let (w, h) = image.dimensions();
+        if w == 0 || h == 0 {
+            return Err(Error::Image("Can not add empty image to atlas".into()));
+        }
+        let (memory, size) = extrude(image, self.padding);
+        let (page, offset) = self.pages.add(context, size, &memory)?;
+        Ok(AtlasImage {
+            page,
+            rect: [offset[0] + self.padding, offset[1] + self.padding, w, h],
+        })
+    }
+
+    /// Adds images to the atlas, returning handles in the same order.
+    ///
+    /// Images are packed from tallest to shortest, which uses less space
+    /// than adding them one by one.
+    pub fn add_all<F, C>(
+        &mut self,
+        context: &mut TextureContext<F, R, C>,
+        images: &[RgbaImage]
+    ) -> Result<Vec<AtlasImage>, Error>
+        where F: gfx::Factory<R>,
+              C: gfx::CommandBuffer<R>
+    {
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by_key(|&i| ::std::cmp::Reverse(images[i].height()));
+        let mut handles = vec![None; images.len()];
+        for i in order {
+            handles[i] = Some(self.add(context, &images[i])?);
+        }
+        Ok(handles.into_iter().map(|h| h.unwrap()).collect())
+    }
+
+    /// Returns the atlas texture containing an image.
+    pub fn texture(&self, image: &AtlasImage) -> &Texture<R> {
+        self.pages.texture(image.page)
+    }
+
+    /// Returns an image as a region of its atlas texture.
+    pub fn region(&self, image: &AtlasImage) -> TextureRegion<R> {
+        TextureRegion::new(self.texture(image).clone(), image.rect)
+    }
+
+    /// Returns the atlas textures.
+    pub fn textures(&self) -> impl Iterator<Item = &Texture<R>> {
+        self.pages.textures()
+    }
+
+    /// Writes added images through the encoder of `GfxGraphics`.
+    ///
+    /// Call this before drawing images added since the last call.
+    pub fn flush_uploads<C>(&mut self, g: &mut GfxGraphics<R, C>)
+        where C: gfx::CommandBuffer<R>
+    {
+        self.pages.flush_uploads(g.encoder);
+    }
+}
+
+// Copies an image with its border pixels repeated into the padding.
+fn extrude(image: &RgbaImage, padding: u32) -> (Vec<u8>, [u32; 2]) {
+    let (w, h) = image.dimensions();
+    let size = [w + 2 * padding, h + 2 * padding];
+    let mut memory = Vec::with_capacity((size[0] * size[1] * 4) as usize);
+    for y in 0..size[1] {
+        let sy = y.saturating_sub(padding).min(h - 1);
+        for x in 0..size[0] {
+            let sx = x.saturating_sub(padding).min(w - 1);
+            memory.extend_from_slice(&image.get_pixel(sx, sy).0);
+        }
+    }
+    (memory, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn borders_are_extruded_into_padding() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, Rgba([1, 1, 1, 1]));
+        image.put_pixel(1, 0, Rgba([2, 2, 2, 2]));
+        let (memory, size) = extrude(&image, 1);
+        assert_eq!(size, [4, 3]);
+        let row = [1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2];
+        assert_eq!(&memory[..16], &row[..]);
+        assert_eq!(&memory[16..32], &row[..]);
+        assert_eq!(&memory[32..], &row[..]);
+    }
+
+    #[test]
+    fn no_padding_copies_the_image() {
+        let image = RgbaImage::from_raw(1, 2, vec![1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        let (memory, size) = extrude(&image, 0);
+        assert_eq!(size, [1, 2]);
+        assert_eq!(memory, image.into_raw());
+    }
+}
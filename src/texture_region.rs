@@ -0,0 +1,202 @@
+//! Sub-rectangles of textures usable as standalone textures.
+
+use gfx;
+use graphics::{DrawState, Graphics};
+
+use back_end::GfxGraphics;
+use {ImageSize, Texture};
+
+/// A rectangle `[x, y, w, h]` in pixels of a texture.
+///
+/// Behaves like a texture of the rectangle size when drawing through
+/// `RegionGraphics`, mapping texture coordinates into the rectangle.
+/// Wrap modes of the sampler apply to the whole texture, not the region.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureRegion<R: gfx::Resources> {
+    texture: Texture<R>,
+    rect: [u32; 4],
+}
+
+impl<R: gfx::Resources> TextureRegion<R> {
+    /// Creates a new region of a texture.
+    ///
+    /// Panics if the rectangle is outside the texture.
+    pub fn new(texture: Texture<R>, rect: [u32; 4]) -> TextureRegion<R> {
+        let (w, h) = texture.get_size();
+        assert!(contains([w, h], rect),
+                "Region {:?} is outside texture of size {:?}", rect, (w, h));
+        TextureRegion {
+            texture,
+            rect,
+        }
+    }
+
+    /// Returns a region relative to this region.
+    ///
+    /// Panics if the rectangle is outside this region.
+    pub fn region(&self, rect: [u32; 4]) -> TextureRegion<R> {
+        assert!(contains([self.rect[2], self.rect[3]], rect),
+                "Region {:?} is outside region of size {:?}", rect,
+                (self.rect[2], self.rect[3]));
+        TextureRegion {
+            texture: self.texture.clone(),
+            rect: [self.rect[0] + rect[0], self.rect[1] + rect[1], rect[2], rect[3]],
+        }
+    }
+
+    /// Returns the texture.
+    pub fn texture(&self) -> &Texture<R> {
+        &self.texture
+    }
+
+    /// Returns the rectangle in the texture.
+    pub fn rect(&self) -> [u32; 4] {
+        self.rect
+    }
+
+    /// Maps texture coordinates of the region to the texture.
+    pub fn map_uv(&self, uv: [f32; 2]) -> [f32; 2] {
+        let (w, h) = self.texture.get_size();
+        [
+            (self.rect[0] as f32 + uv[0] * self.rect[2] as f32) / w as f32,
+            (self.rect[1] as f32 + uv[1] * self.rect[3] as f32) / h as f32,
+        ]
+    }
+}
+
+impl<R: gfx::Resources> From<Texture<R>> for TextureRegion<R> {
+    fn from(texture: Texture<R>) -> TextureRegion<R> {
+        let (w, h) = texture.get_size();
+        TextureRegion {
+            texture,
+            rect: [0, 0, w, h],
+        }
+    }
+}
+
+impl<R: gfx::Resources> ImageSize for TextureRegion<R> {
+    fn get_size(&self) -> (u32, u32) {
+        (self.rect[2], self.rect[3])
+    }
+}
+
+// Returns true if a rectangle `[x, y, w, h]` fits inside a size.
+pub(crate) fn contains(size: [u32; 2], rect: [u32; 4]) -> bool {
+    let fits = |offset: u32, len: u32, size: u32| match offset.checked_add(len) {
+        Some(end) => end <= size,
+        None => false,
+    };
+    fits(rect[0], rect[2], size[0]) && fits(rect[1], rect[3], size[1])
+}
+
+/// Renders 2D graphics using `TextureRegion` as texture type.
+///
+/// Created with `GfxGraphics::regions`.
+/// Textured triangles are forwarded to `GfxGraphics` with the texture
+/// coordinates mapped into the regions, so regions of the same texture
+/// are batched into one draw call.
+pub struct RegionGraphics<'b, 'a: 'b, R, C>
+    where R: gfx::Resources + 'a,
+          C: gfx::CommandBuffer<R> + 'a,
+          R::Buffer: 'a,
+          R::Shader: 'a,
+          R::Program: 'a,
+          R::Texture: 'a,
+          R::Sampler: 'a
+{
+    g: &'b mut GfxGraphics<'a, R, C>,
+    // Reused buffer for mapped texture coordinates.
+    uvs: Vec<[f32; 2]>,
+}
+
+impl<'b, 'a, R, C> RegionGraphics<'b, 'a, R, C>
+    where R: gfx::Resources,
+          C: gfx::CommandBuffer<R>,
+          R::Buffer: 'a,
+          R::Shader: 'a,
+          R::Program: 'a,
+          R::Texture: 'a,
+          R::Sampler: 'a
+{
+    /// Creates a new object rendering through `GfxGraphics`.
+    pub fn new(g: &'b mut GfxGraphics<'a, R, C>) -> Self {
+        RegionGraphics {
+            g,
+            uvs: vec![],
+        }
+    }
+
+    /// Returns the underlying `GfxGraphics`.
+    pub fn graphics(&mut self) -> &mut GfxGraphics<'a, R, C> {
+        self.g
+    }
+}
+
+impl<'b, 'a, R, C> Graphics for RegionGraphics<'b, 'a, R, C>
+    where R: gfx::Resources,
+          C: gfx::CommandBuffer<R>,
+          R::Buffer: 'a,
+          R::Shader: 'a,
+          R::Program: 'a,
+          R::Texture: 'a,
+          R::Sampler: 'a
+{
+    type Texture = TextureRegion<R>;
+
+    fn clear_color(&mut self, color: [f32; 4]) {
+        self.g.clear_color(color)
+    }
+
+    fn clear_stencil(&mut self, value: u8) {
+        self.g.clear_stencil(value)
+    }
+
+    fn tri_list<F>(&mut self, draw_state: &DrawState, color: &[f32; 4], f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]]))
+    {
+        self.g.tri_list(draw_state, color, f)
+    }
+
+    fn tri_list_c<F>(&mut self, draw_state: &DrawState, f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]]))
+    {
+        self.g.tri_list_c(draw_state, f)
+    }
+
+    fn tri_list_uv<F>(
+        &mut self,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        texture: &TextureRegion<R>,
+        mut f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]]))
+    {
+        let uvs = &mut self.uvs;
+        self.g.tri_list_uv(draw_state, color, &texture.texture, |g| {
+            f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]]| {
+                uvs.clear();
+                uvs.extend(texture_coords.iter().map(|&uv| texture.map_uv(uv)));
+                g(vertices, uvs);
+            })
+        })
+    }
+
+    fn tri_list_uv_c<F>(
+        &mut self,
+        draw_state: &DrawState,
+        texture: &TextureRegion<R>,
+        mut f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]]))
+    {
+        let uvs = &mut self.uvs;
+        self.g.tri_list_uv_c(draw_state, &texture.texture, |g| {
+            f(&mut |vertices: &[[f32; 2]], texture_coords: &[[f32; 2]], colors: &[[f32; 4]]| {
+                uvs.clear();
+                uvs.extend(texture_coords.iter().map(|&uv| texture.map_uv(uv)));
+                g(vertices, uvs, colors);
+            })
+        })
+    }
+}
@@ -0,0 +1,564 @@
+//! Recording and replaying of `Graphics` calls.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::{FromStr, SplitWhitespace};
+
+use gfx;
+use graphics::draw_state::{Blend, Stencil};
+use graphics::{DrawState, Graphics};
+
+use {ImageSize, Texture};
+
+// The first line of trace files.
+const HEADER: &str = "gfx_graphics-trace 1";
+
+/// Vertices passed to a `Graphics` back-end in one callback.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceChunk {
+    /// Positions of triangle vertices.
+    pub vertices: Vec<[f32; 2]>,
+    /// Texture coordinates, empty for calls without a texture.
+    pub texture_coords: Vec<[f32; 2]>,
+    /// Colors per vertex, empty for calls with a uniform color.
+    pub colors: Vec<[f32; 4]>,
+}
+
+/// A recorded call to a `Graphics` back-end.
+///
+/// Draw states include scissor rectangles pushed on `GfxGraphics`.
+/// Textures are referred to by index into `Trace::textures`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceCall {
+    /// `Graphics::clear_color`.
+    ClearColor([f32; 4]),
+    /// `Graphics::clear_stencil`.
+    ClearStencil(u8),
+    /// `Graphics::tri_list`.
+    TriList {
+        /// The draw state.
+        draw_state: DrawState,
+        /// The uniform color.
+        color: [f32; 4],
+        /// The vertices of each callback.
+        chunks: Vec<TraceChunk>,
+    },
+    /// `Graphics::tri_list_c`.
+    TriListC {
+        /// The draw state.
+        draw_state: DrawState,
+        /// The vertices and colors of each callback.
+        chunks: Vec<TraceChunk>,
+    },
+    /// `Graphics::tri_list_uv`.
+    TriListUv {
+        /// The draw state.
+        draw_state: DrawState,
+        /// The uniform color.
+        color: [f32; 4],
+        /// The texture index.
+        texture: usize,
+        /// The vertices and texture coordinates of each callback.
+        chunks: Vec<TraceChunk>,
+    },
+    /// `Graphics::tri_list_uv_c`.
+    TriListUvC {
+        /// The draw state.
+        draw_state: DrawState,
+        /// The texture index.
+        texture: usize,
+        /// The vertices, texture coordinates and colors of each callback.
+        chunks: Vec<TraceChunk>,
+    },
+}
+
+/// A trace of `Graphics` calls, e.g. for bug reports and regression tests.
+///
+/// Record a trace with `Gfx2d::start_recording`,
+/// save it as a text file with `save`,
+/// and feed it back into any `Graphics` back-end with `replay`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    /// The sizes of the textures used by calls, in order of first use.
+    pub textures: Vec<[u32; 2]>,
+    /// The recorded calls.
+    pub calls: Vec<TraceCall>,
+}
+
+impl Trace {
+    /// Creates an empty trace.
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    /// Replays the calls on a `Graphics` back-end.
+    ///
+    /// Texture indices refer to `textures`,
+    /// e.g. placeholders created from the sizes in `Trace::textures`.
+    ///
+    /// Panics if a call uses a texture missing from `textures`.
+    pub fn replay<G>(&self, g: &mut G, textures: &[&G::Texture])
+        where G: Graphics
+    {
+        for call in &self.calls {
+            match *call {
+                TraceCall::ClearColor(color) => g.clear_color(color),
+                TraceCall::ClearStencil(value) => g.clear_stencil(value),
+                TraceCall::TriList { ref draw_state, ref color, ref chunks } => {
+                    g.tri_list(draw_state, color, |f| {
+                        for chunk in chunks {
+                            f(&chunk.vertices);
+                        }
+                    })
+                }
+                TraceCall::TriListC { ref draw_state, ref chunks } => {
+                    g.tri_list_c(draw_state, |f| {
+                        for chunk in chunks {
+                            f(&chunk.vertices, &chunk.colors);
+                        }
+                    })
+                }
+                TraceCall::TriListUv { ref draw_state, ref color, texture, ref chunks } => {
+                    g.tri_list_uv(draw_state, color, textures[texture], |f| {
+                        for chunk in chunks {
+                            f(&chunk.vertices, &chunk.texture_coords);
+                        }
+                    })
+                }
+                TraceCall::TriListUvC { ref draw_state, texture, ref chunks } => {
+                    g.tri_list_uv_c(draw_state, textures[texture], |f| {
+                        for chunk in chunks {
+                            f(&chunk.vertices, &chunk.texture_coords, &chunk.colors);
+                        }
+                    })
+                }
+            }
+        }
+    }
+
+    /// Saves the trace to a text file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    /// Loads a trace from a text file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Trace> {
+        Trace::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the trace as text.
+    ///
+    /// Each call is written on one line, followed by lines for its vertices.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", HEADER)?;
+        for size in &self.textures {
+            writeln!(w, "texture {} {}", size[0], size[1])?;
+        }
+        for call in &self.calls {
+            let chunks = match *call {
+                TraceCall::ClearColor(color) => {
+                    writeln!(w, "clear_color {}", Floats(&color))?;
+                    continue;
+                }
+                TraceCall::ClearStencil(value) => {
+                    writeln!(w, "clear_stencil {}", value)?;
+                    continue;
+                }
+                TraceCall::TriList { ref draw_state, ref color, ref chunks } => {
+                    writeln!(w, "tri_list {} color {}",
+                        WriteDrawState(draw_state), Floats(color))?;
+                    chunks
+                }
+                TraceCall::TriListC { ref draw_state, ref chunks } => {
+                    writeln!(w, "tri_list_c {}", WriteDrawState(draw_state))?;
+                    chunks
+                }
+                TraceCall::TriListUv { ref draw_state, ref color, texture, ref chunks } => {
+                    writeln!(w, "tri_list_uv {} color {} texture {}",
+                        WriteDrawState(draw_state), Floats(color), texture)?;
+                    chunks
+                }
+                TraceCall::TriListUvC { ref draw_state, texture, ref chunks } => {
+                    writeln!(w, "tri_list_uv_c {} texture {}",
+                        WriteDrawState(draw_state), texture)?;
+                    chunks
+                }
+            };
+            for chunk in chunks {
+                writeln!(w, "chunk {}", chunk.vertices.len())?;
+                for (i, v) in chunk.vertices.iter().enumerate() {
+                    write!(w, "{}", Floats(v))?;
+                    if let Some(uv) = chunk.texture_coords.get(i) {
+                        write!(w, " {}", Floats(uv))?;
+                    }
+                    if let Some(color) = chunk.colors.get(i) {
+                        write!(w, " {}", Floats(color))?;
+                    }
+                    writeln!(w)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a trace written by `write`.
+    pub fn read<B: BufRead>(r: B) -> io::Result<Trace> {
+        let mut lines = r.lines().enumerate().peekable();
+        let header = match lines.next() {
+            None => String::new(),
+            Some((_, line)) => line?,
+        };
+        if header.trim() != HEADER {
+            return Err(invalid(1, "Expected trace header"));
+        }
+
+        let mut trace = Trace::new();
+        while let Some((i, line)) = lines.next() {
+            let line = line?;
+            let n = i + 1;
+            let mut tokens = Tokens { tokens: line.split_whitespace(), line: n };
+            let (mut call, uv, colors) = match tokens.next() {
+                None => continue,
+                Some("texture") => {
+                    trace.textures.push([tokens.parse()?, tokens.parse()?]);
+                    tokens.end()?;
+                    continue;
+                }
+                Some("clear_color") => {
+                    trace.calls.push(TraceCall::ClearColor(tokens.floats()?));
+                    tokens.end()?;
+                    continue;
+                }
+                Some("clear_stencil") => {
+                    trace.calls.push(TraceCall::ClearStencil(tokens.parse()?));
+                    tokens.end()?;
+                    continue;
+                }
+                Some("chunk") => return Err(invalid(n, "Chunk without a call")),
+                Some("tri_list") => {
+                    let draw_state = tokens.draw_state()?;
+                    tokens.expect("color")?;
+                    let color = tokens.floats()?;
+                    (TraceCall::TriList {
+                        draw_state, color, chunks: vec![]
+                    }, false, false)
+                }
+                Some("tri_list_c") => {
+                    let draw_state = tokens.draw_state()?;
+                    (TraceCall::TriListC { draw_state, chunks: vec![] }, false, true)
+                }
+                Some("tri_list_uv") => {
+                    let draw_state = tokens.draw_state()?;
+                    tokens.expect("color")?;
+                    let color = tokens.floats()?;
+                    tokens.expect("texture")?;
+                    let texture = tokens.parse()?;
+                    (TraceCall::TriListUv {
+                        draw_state, color, texture, chunks: vec![]
+                    }, true, false)
+                }
+                Some("tri_list_uv_c") => {
+                    let draw_state = tokens.draw_state()?;
+                    tokens.expect("texture")?;
+                    let texture = tokens.parse()?;
+                    (TraceCall::TriListUvC {
+                        draw_state, texture, chunks: vec![]
+                    }, true, true)
+                }
+                Some(token) => return Err(invalid(n, &format!("Unknown call `{}`", token))),
+            };
+            tokens.end()?;
+
+            let chunks = match call {
+                TraceCall::TriList { ref mut chunks, .. } |
+                TraceCall::TriListC { ref mut chunks, .. } |
+                TraceCall::TriListUv { ref mut chunks, .. } |
+                TraceCall::TriListUvC { ref mut chunks, .. } => chunks,
+                _ => unreachable!(),
+            };
+            // Read chunks until the next call.
+            while let Some(&(_, Ok(ref line))) = lines.peek() {
+                if !line.starts_with("chunk") {
+                    break;
+                }
+                let (i, line) = lines.next().unwrap();
+                let line = line?;
+                let mut tokens = Tokens { tokens: line.split_whitespace(), line: i + 1 };
+                tokens.expect("chunk")?;
+                let count: usize = tokens.parse()?;
+                tokens.end()?;
+                let mut chunk = TraceChunk::default();
+                for _ in 0..count {
+                    let (i, line) = match lines.next() {
+                        None => return Err(invalid(i + 1, "Chunk ends early")),
+                        Some(line) => line,
+                    };
+                    let line = line?;
+                    let mut tokens = Tokens { tokens: line.split_whitespace(), line: i + 1 };
+                    chunk.vertices.push(tokens.floats()?);
+                    if uv {
+                        chunk.texture_coords.push(tokens.floats()?);
+                    }
+                    if colors {
+                        chunk.colors.push(tokens.floats()?);
+                    }
+                    tokens.end()?;
+                }
+                chunks.push(chunk);
+            }
+            trace.calls.push(call);
+        }
+
+        for call in &trace.calls {
+            match *call {
+                TraceCall::TriListUv { texture, .. } |
+                TraceCall::TriListUvC { texture, .. } if texture >= trace.textures.len() => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                        format!("Texture {} is not declared", texture)));
+                }
+                _ => {}
+            }
+        }
+        Ok(trace)
+    }
+}
+
+/// Records `Graphics` calls of `GfxGraphics`.
+pub(crate) struct TraceRecorder<R: gfx::Resources> {
+    pub(crate) trace: Trace,
+    /// Indices of textures used so far.
+    textures: HashMap<gfx::handle::RawTexture<R>, usize>,
+}
+
+impl<R: gfx::Resources> TraceRecorder<R> {
+    pub(crate) fn new() -> TraceRecorder<R> {
+        TraceRecorder {
+            trace: Trace::new(),
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Returns the index of a texture, adding it on first use.
+    pub(crate) fn texture(&mut self, texture: &Texture<R>) -> usize {
+        use gfx::memory::Typed;
+
+        let textures = &mut self.trace.textures;
+        *self.textures.entry(texture.surface.raw().clone()).or_insert_with(|| {
+            let (w, h) = texture.get_size();
+            textures.push([w, h]);
+            textures.len() - 1
+        })
+    }
+
+    /// Adds a call to the trace.
+    pub(crate) fn record(&mut self, call: TraceCall) {
+        self.trace.calls.push(call);
+    }
+}
+
+// Creates an error for invalid trace data.
+fn invalid(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", line, msg))
+}
+
+// Parses the tokens of a line.
+struct Tokens<'a> {
+    tokens: SplitWhitespace<'a>,
+    line: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn token(&mut self) -> io::Result<&'a str> {
+        self.tokens.next().ok_or_else(|| invalid(self.line, "Line ends early"))
+    }
+
+    fn expect(&mut self, expected: &str) -> io::Result<()> {
+        let token = self.token()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(invalid(self.line, &format!("Expected `{}`, found `{}`", expected, token)))
+        }
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        match self.tokens.next() {
+            None => Ok(()),
+            Some(token) => Err(invalid(self.line, &format!("Unexpected `{}`", token))),
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self) -> io::Result<T> {
+        let token = self.token()?;
+        token.parse().map_err(|_| invalid(self.line, &format!("Invalid number `{}`", token)))
+    }
+
+    fn floats<A: Default + AsMut<[f32]>>(&mut self) -> io::Result<A> {
+        let mut floats = A::default();
+        for x in floats.as_mut() {
+            *x = self.parse()?;
+        }
+        Ok(floats)
+    }
+
+    fn draw_state(&mut self) -> io::Result<DrawState> {
+        let mut draw_state = DrawState::default();
+        self.expect("scissor")?;
+        draw_state.scissor = match self.token()? {
+            "none" => None,
+            x => Some([
+                x.parse().map_err(|_| invalid(self.line, "Invalid scissor"))?,
+                self.parse()?, self.parse()?, self.parse()?,
+            ]),
+        };
+        self.expect("stencil")?;
+        draw_state.stencil = match self.token()? {
+            "none" => None,
+            "clip" => Some(Stencil::Clip(self.parse()?)),
+            "inside" => Some(Stencil::Inside(self.parse()?)),
+            "outside" => Some(Stencil::Outside(self.parse()?)),
+            "increment" => Some(Stencil::Increment),
+            token => return Err(invalid(self.line, &format!("Unknown stencil `{}`", token))),
+        };
+        self.expect("blend")?;
+        draw_state.blend = match self.token()? {
+            "none" => None,
+            "alpha" => Some(Blend::Alpha),
+            "add" => Some(Blend::Add),
+            "multiply" => Some(Blend::Multiply),
+            "invert" => Some(Blend::Invert),
+            "lighter" => Some(Blend::Lighter),
+            token => return Err(invalid(self.line, &format!("Unknown blend `{}`", token))),
+        };
+        Ok(draw_state)
+    }
+}
+
+// Writes floats separated by spaces, in a format that parses back exactly.
+struct Floats<'a>(&'a [f32]);
+
+impl<'a> ::std::fmt::Display for Floats<'a> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        for (i, x) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:?}", x)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes a draw state as `scissor .. stencil .. blend ..`.
+struct WriteDrawState<'a>(&'a DrawState);
+
+impl<'a> ::std::fmt::Display for WriteDrawState<'a> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self.0.scissor {
+            None => write!(f, "scissor none")?,
+            Some(r) => write!(f, "scissor {} {} {} {}", r[0], r[1], r[2], r[3])?,
+        }
+        match self.0.stencil {
+            None => write!(f, " stencil none")?,
+            Some(Stencil::Clip(val)) => write!(f, " stencil clip {}", val)?,
+            Some(Stencil::Inside(val)) => write!(f, " stencil inside {}", val)?,
+            Some(Stencil::Outside(val)) => write!(f, " stencil outside {}", val)?,
+            Some(Stencil::Increment) => write!(f, " stencil increment")?,
+        }
+        let blend = match self.0.blend {
+            None => "none",
+            Some(Blend::Alpha) => "alpha",
+            Some(Blend::Add) => "add",
+            Some(Blend::Multiply) => "multiply",
+            Some(Blend::Invert) => "invert",
+            Some(Blend::Lighter) => "lighter",
+        };
+        write!(f, " blend {}", blend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace() -> Trace {
+        let clipped = DrawState {
+            scissor: Some([1, 2, 30, 40]),
+            stencil: Some(Stencil::Inside(3)),
+            blend: Some(Blend::Multiply),
+        };
+        let chunk = TraceChunk {
+            vertices: vec![[0.1, -2.5], [1e-7, 3.0], [0.0, 1.0]],
+            texture_coords: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+            colors: vec![],
+        };
+        Trace {
+            textures: vec![[64, 32]],
+            calls: vec![
+                TraceCall::ClearColor([0.2, 0.3, 0.4, 1.0]),
+                TraceCall::ClearStencil(7),
+                TraceCall::TriList {
+                    draw_state: DrawState::default(),
+                    color: [1.0, 0.5, 0.25, 0.125],
+                    chunks: vec![TraceChunk {
+                        vertices: vec![[0.0, 0.0]; 3],
+                        ..TraceChunk::default()
+                    }],
+                },
+                TraceCall::TriListC {
+                    draw_state: clipped,
+                    chunks: vec![
+                        TraceChunk {
+                            vertices: chunk.vertices.clone(),
+                            texture_coords: vec![],
+                            colors: vec![[0.5; 4]; 3],
+                        },
+                        TraceChunk::default(),
+                    ],
+                },
+                TraceCall::TriListUv {
+                    draw_state: DrawState::new_clip(),
+                    color: [1.0; 4],
+                    texture: 0,
+                    chunks: vec![chunk.clone()],
+                },
+                TraceCall::TriListUvC {
+                    draw_state: DrawState::new_increment(),
+                    texture: 0,
+                    chunks: vec![TraceChunk { colors: vec![[0.1; 4]; 3], ..chunk }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn written_traces_read_back_exactly() {
+        let trace = trace();
+        let mut text = vec![];
+        trace.write(&mut text).unwrap();
+        assert_eq!(Trace::read(&text[..]).unwrap(), trace);
+    }
+
+    #[test]
+    fn saved_traces_load_back_exactly() {
+        let trace = trace();
+        let path = ::std::env::temp_dir().join("gfx_graphics_trace_round_trip.txt");
+        trace.save(&path).unwrap();
+        let loaded = Trace::load(&path);
+        let _ = ::std::fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), trace);
+    }
+
+    #[test]
+    fn unknown_header_is_rejected() {
+        assert!(Trace::read(&b"gfx_graphics-trace 2\n"[..]).is_err());
+    }
+}
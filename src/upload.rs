@@ -0,0 +1,69 @@
+//! Texture uploads queued for the encoder used when drawing.
+
+use gfx;
+use gfx::format::{R8_G8_B8_A8, Rgba8};
+
+// An RGBA8 region waiting to be written to a texture.
+struct Upload<R: gfx::Resources> {
+    surface: gfx::handle::Texture<R, R8_G8_B8_A8>,
+    offset: [u32; 2],
+    size: [u32; 2],
+    memory: Vec<u8>,
+}
+
+/// Texture uploads of a texture cache.
+///
+/// Uploads are written through the encoder of `GfxGraphics`
+/// before the draw calls sampling them are submitted.
+pub(crate) struct UploadQueue<R: gfx::Resources> {
+    uploads: Vec<Upload<R>>,
+}
+
+impl<R: gfx::Resources> UploadQueue<R> {
+    /// Creates an empty queue.
+    pub(crate) fn new() -> UploadQueue<R> {
+        UploadQueue {
+            uploads: vec![],
+        }
+    }
+
+    /// Queues an RGBA8 region of a texture.
+    pub(crate) fn push(
+        &mut self,
+        surface: &gfx::handle::Texture<R, R8_G8_B8_A8>,
+        offset: [u32; 2],
+        size: [u32; 2],
+        memory: &[u8]
+    ) {
+        self.uploads.push(Upload {
+            surface: surface.clone(),
+            offset,
+            size,
+            memory: memory.to_vec(),
+        });
+    }
+
+    /// Writes queued uploads through an encoder.
+    pub(crate) fn submit<C>(&mut self, encoder: &mut gfx::Encoder<R, C>)
+        where C: gfx::CommandBuffer<R>
+    {
+        for upload in self.uploads.drain(..) {
+            let Upload { surface, offset, size, memory } = upload;
+            let img_info = gfx::texture::ImageInfoCommon {
+                xoffset: offset[0] as u16,
+                yoffset: offset[1] as u16,
+                zoffset: 0,
+                width: size[0] as u16,
+                height: size[1] as u16,
+                depth: 0,
+                format: (),
+                mipmap: 0,
+            };
+            let data = gfx::memory::cast_slice(&memory);
+            if let Err(err) = encoder.update_texture::<_, Rgba8>(&surface, None, img_info, data) {
+                error!("Could not upload texture region {:?} of size {:?}: {:?}",
+                    offset, size, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+extern crate gfx_device_gl;
+extern crate gfx_graphics;
+
+use gfx_device_gl::Resources;
+use gfx_graphics::Gfx2d;
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn gfx2d_is_send() {
+    // Checked at compile time, so a field making `Gfx2d` !Send fails the build.
+    assert_send::<Gfx2d<Resources>>();
+}